@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+use field::Field;
+use q_learning::{State, DISCOUNT_RATE};
+use stats::RunningStat;
+use table_io;
+
+//1つの状態番号にまとめられた実際の状況のばらつき
+#[derive(Default)]
+pub struct StateFeatures {
+    pub enemies: RunningStat,
+    pub nearest_distance: RunningStat,
+    pub bullets: RunningStat,
+    pub returns: RunningStat,
+}
+
+struct Step {
+    state: State,
+    reward: f64,
+}
+
+//エピソード中の各ステップを記録し、エピソードの終わりに割引収益を状態ごとに集計する
+#[derive(Default)]
+pub struct AliasingStats {
+    pub states: HashMap<State, StateFeatures>,
+    steps: Vec<Step>,
+}
+
+impl AliasingStats {
+    pub fn new() -> AliasingStats {
+        AliasingStats::default()
+    }
+
+    //行動する直前のFieldを渡す
+    pub fn observe(&mut self, field: &Field, state: State) {
+        let features = self.states.entry(state).or_insert(StateFeatures::default());
+        features.enemies.push(field.enemy_list.len() as f64);
+        if let Some(distance) = field.nearest_enemy_distance() {
+            features.nearest_distance.push(distance as f64);
+        }
+        features.bullets.push(field.player.remain_bullet as f64);
+        self.steps.push(Step {
+            state: state,
+            reward: 0.0,
+        });
+    }
+
+    pub fn reward(&mut self, reward: f64) {
+        if let Some(step) = self.steps.last_mut() {
+            step.reward += reward;
+        }
+    }
+
+    pub fn end_episode(&mut self) {
+        let mut ret = 0.0;
+        for step in self.steps.iter().rev() {
+            ret = step.reward + DISCOUNT_RATE * ret;
+            self.states.get_mut(&step.state).unwrap().returns.push(ret);
+        }
+        self.steps.clear();
+    }
+
+    //収益の標準偏差が大きい順。同じ状態に違う状況が混ざっているほど大きくなる
+    pub fn most_aliased(&self) -> Vec<(State, &StateFeatures)> {
+        let mut states: Vec<(State, &StateFeatures)> =
+            self.states.iter().map(|(&state, features)| (state, features)).collect();
+        states.sort_by(|a, b| {
+            b.1.returns.std().partial_cmp(&a.1.returns.std()).unwrap()
+        });
+        states
+    }
+
+    pub fn print(&self, top: usize) {
+        println!(
+            "{:<10} {:>8} {:>12} {:>12} {:>16} {:>16} {:>12}",
+            "state",
+            "visits",
+            "return mean",
+            "return std",
+            "enemies mean/sd",
+            "distance mean/sd",
+            "bullets sd"
+        );
+        for (state, features) in self.most_aliased().into_iter().take(top) {
+            let perception = table_io::perception_column(state);
+            println!(
+                "{:<10} {:>8} {:>12.2} {:>12.2} {:>8.2}/{:<7.2} {:>8.1}/{:<7.1} {:>12.2}",
+                if perception.is_empty() {
+                    format!("{:x}", state)
+                } else {
+                    perception
+                },
+                features.enemies.count,
+                features.returns.mean,
+                features.returns.std(),
+                features.enemies.mean,
+                features.enemies.std(),
+                features.nearest_distance.mean,
+                features.nearest_distance.std(),
+                features.bullets.std()
+            );
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use toml;
+use field::Field;
+use file_reader::Pattern;
+use training_log::Outcome;
+
+fn default_arena_size() -> u32 {
+    400
+}
+
+//ステージの難しさを変える倍率。パターンに掛けるので、どのパターンにも使える
+#[derive(Deserialize, Copy, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Modifiers {
+    //敵の速さ
+    pub enemy_speed: f32,
+    //出現の間隔(dt)。小さいほど詰まって出てくる
+    pub spawn_interval: f32,
+}
+
+impl Default for Modifiers {
+    fn default() -> Modifiers {
+        Modifiers {
+            enemy_speed: 1.0,
+            spawn_interval: 1.0,
+        }
+    }
+}
+
+impl Modifiers {
+    pub fn apply(&self, pattern: &Pattern) -> Pattern {
+        let mut pattern = pattern.clone();
+        for location in pattern.locations.iter_mut() {
+            location.speed *= self.enemy_speed;
+            location.vector.x *= self.enemy_speed;
+            location.vector.y *= self.enemy_speed;
+            //dt=0 は同時に出るという意味なので、0でないものは0にしない
+            if location.dt != 0 {
+                let dt = (location.dt as f32 * self.spawn_interval).round() as usize;
+                location.dt = dt.max(1);
+            }
+        }
+        pattern
+    }
+}
+
+//キャンペーンファイル(TOML)の [[stage]] 1つぶん
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StageConfig {
+    name: Option<String>,
+    //なければ既定のパターン(./enemy_appearance.pat か埋め込みのもの)
+    pattern: Option<String>,
+    #[serde(default = "default_arena_size")]
+    width: u32,
+    #[serde(default = "default_arena_size")]
+    height: u32,
+    #[serde(default)]
+    modifiers: Modifiers,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CampaignConfig {
+    name: Option<String>,
+    #[serde(rename = "stage")]
+    stages: Vec<StageConfig>,
+}
+
+pub struct Stage {
+    pub name: String,
+    //倍率を掛けたあとのパターン
+    pub pattern: Pattern,
+    pub arena: (u32, u32),
+}
+
+//順に遊ぶステージの並び。得点は次のステージに持ち越す
+pub struct Campaign {
+    pub name: String,
+    pub stages: Vec<Stage>,
+}
+
+impl Campaign {
+    //パターンもここで読んでおき、途中のステージで読めずに止まることがないようにする
+    pub fn load(path: &str) -> Result<Campaign, Box<Error>> {
+        let mut s = String::new();
+        File::open(path)?.read_to_string(&mut s)?;
+        let config: CampaignConfig = toml::from_str(&s).map_err(|err| format!("{}: {}", path, err))?;
+        if config.stages.is_empty() {
+            return Err(format!("{}: campaign has no stages", path).into());
+        }
+        let mut stages = Vec::new();
+        for stage in config.stages.iter() {
+            if stage.width == 0 || stage.height == 0 {
+                return Err(format!("{}: arena must not be empty", path).into());
+            }
+            let pattern = Pattern::load(stage.pattern.as_ref().map(|p| p.as_str()))?;
+            stages.push(Stage {
+                name: stage.name.clone().unwrap_or_else(|| pattern.name.clone()),
+                pattern: stage.modifiers.apply(&pattern),
+                arena: (stage.width, stage.height),
+            });
+        }
+        Ok(Campaign {
+            name: config.name.unwrap_or_else(|| path.to_string()),
+            stages: stages,
+        })
+    }
+
+    //すべてのステージが収まる画面の大きさ
+    pub fn max_arena(&self) -> (u32, u32) {
+        self.stages.iter().fold((0, 0), |(w, h), stage| {
+            (w.max(stage.arena.0), h.max(stage.arena.1))
+        })
+    }
+}
+
+pub struct StageResult {
+    pub stage: usize,
+    pub name: String,
+    pub pattern: String,
+    pub steps: u64,
+    //このステージで取った得点
+    pub score: u64,
+    //持ち越しを含めた得点
+    pub total_score: u64,
+    pub kills: u64,
+    pub max_chain: u64,
+    pub outcome: Outcome,
+}
+
+//キャンペーン1回ぶんの進み具合。人間でもエージェントでも field を動かし、
+//ステージが終わったら finish_stage を呼ぶ
+pub struct Run<'a> {
+    campaign: &'a Campaign,
+    pub stage: usize,
+    pub field: Field,
+    pub results: Vec<StageResult>,
+}
+
+fn stage_field(stage: &Stage, score: u64) -> Field {
+    let (width, height) = stage.arena;
+    let mut field = Field::new(width, height, &stage.pattern);
+    field.score = score;
+    field
+}
+
+impl<'a> Run<'a> {
+    pub fn new(campaign: &'a Campaign) -> Run<'a> {
+        Run {
+            campaign: campaign,
+            stage: 0,
+            field: stage_field(&campaign.stages[0], 0),
+            results: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> &Stage {
+        &self.campaign.stages[self.stage]
+    }
+
+    //今のステージの結果を記録し、クリアしていて次があればそのステージを始める
+    pub fn finish_stage(&mut self) -> &StageResult {
+        let carried = self.results.last().map(|r| r.total_score).unwrap_or(0);
+        let outcome = self.field.outcome();
+        self.results.push(StageResult {
+            stage: self.stage,
+            name: self.current().name.clone(),
+            pattern: self.field.pattern_name().to_string(),
+            steps: self.field.tick,
+            score: self.field.score - carried,
+            total_score: self.field.score,
+            kills: self.field.kills,
+            max_chain: self.field.max_chain,
+            outcome: outcome,
+        });
+        if outcome == Outcome::Cleared && self.stage + 1 < self.campaign.stages.len() {
+            self.stage += 1;
+            self.field = stage_field(&self.campaign.stages[self.stage], self.field.score);
+        }
+        self.results.last().unwrap()
+    }
+
+    //やられたか時間切れか、最後のステージまでクリアしたら終わり
+    pub fn is_over(&self) -> bool {
+        match self.results.last() {
+            Some(result) => {
+                result.outcome != Outcome::Cleared || result.stage + 1 == self.campaign.stages.len()
+            }
+            None => false,
+        }
+    }
+}
+
+//名前やパスに , や " が入っていても列がずれないよう、必要なら""で囲む
+fn csv_field(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') || s.contains('\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+//ステージごとの結果を campaign_log.csv に追記する
+pub struct StageLog {
+    file: File,
+}
+
+impl StageLog {
+    pub fn open(path: &str) -> io::Result<StageLog> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(
+                file,
+                "episode,campaign,stage,name,pattern,steps,score,total_score,kills,max_chain,outcome"
+            )?;
+        }
+        Ok(StageLog { file: file })
+    }
+
+    pub fn write(&mut self, episode: usize, campaign: &str, result: &StageResult) -> io::Result<()> {
+        writeln!(
+            self.file,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            episode,
+            csv_field(campaign),
+            result.stage,
+            csv_field(&result.name),
+            csv_field(&result.pattern),
+            result.steps,
+            result.score,
+            result.total_score,
+            result.kills,
+            result.max_chain,
+            result.outcome
+        )?;
+        self.file.flush()
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom};
+use std::path::Path;
+use bincode;
+use serde::Serialize;
+use q_learning::{self, LegacyQTable, QTable};
+use rng::XorShift;
+
+pub const CHECKPOINT_FILE: &str = "checkpoint.bin";
+pub const BEST_CHECKPOINT_FILE: &str = "checkpoint_best.bin";
+//checkpoint.binの先頭に置く印。これが無ければQ値に訪問回数が無い古い形式として読む
+const CHECKPOINT_MAGIC: u64 = 0x4348_4543_4B50_0002;
+
+//学習を途中から再開するのに必要な状態
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub episode: usize,
+    pub rng: XorShift,
+    pub q_table: QTable,
+    pub best_score: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct LegacyCheckpoint {
+    episode: usize,
+    rng: XorShift,
+    q_table: LegacyQTable,
+    best_score: Option<f64>,
+}
+
+impl Checkpoint {
+    pub fn new(q_table: QTable, rng: XorShift) -> Checkpoint {
+        Checkpoint {
+            episode: 0,
+            rng: rng,
+            q_table: q_table,
+            best_score: None,
+        }
+    }
+
+    //チェックポイントが無ければNone、壊れていればエラーを返す
+    pub fn load(path: &str) -> bincode::Result<Option<Checkpoint>> {
+        if !Path::new(path).exists() {
+            return Ok(None);
+        }
+        let mut file = File::open(path)?;
+        let magic: u64 = bincode::deserialize_from(&mut file).unwrap_or(0);
+        if magic == CHECKPOINT_MAGIC {
+            return bincode::deserialize_from(file).map(Some);
+        }
+        file.seek(SeekFrom::Start(0))?;
+        let legacy: LegacyCheckpoint = bincode::deserialize_from(file)?;
+        Ok(Some(Checkpoint {
+            episode: legacy.episode,
+            rng: legacy.rng,
+            q_table: q_learning::from_legacy(legacy.q_table),
+            best_score: legacy.best_score,
+        }))
+    }
+
+    pub fn save(&self, path: &str) -> bincode::Result<()> {
+        write_atomic(path, &(CHECKPOINT_MAGIC, self))
+    }
+
+    //評価スコアが過去最高ならtrueを返す
+    pub fn record_evaluation(&mut self, score: f64) -> bool {
+        match self.best_score {
+            Some(best) if best >= score => false,
+            _ => {
+                self.best_score = Some(score);
+                true
+            }
+        }
+    }
+}
+
+//一時ファイルに書き切ってからrenameするので、途中で落ちても前のファイルは壊れない
+pub fn write_atomic<T: Serialize>(path: &str, value: &T) -> bincode::Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    {
+        let mut file = File::create(&tmp_path)?;
+        bincode::serialize_into(&mut file, value)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+//aがbより後に書き換えられていればtrue。aが無ければfalse
+pub fn is_newer(a: &str, b: &str) -> io::Result<bool> {
+    let modified = match fs::metadata(a) {
+        Ok(metadata) => metadata.modified()?,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    Ok(modified > fs::metadata(b)?.modified()?)
+}
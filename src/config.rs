@@ -0,0 +1,49 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read};
+use toml;
+use endless::{self, Difficulty};
+use file_reader::{Pattern, PatternError};
+
+pub const CONFIG_FILE: &'static str = "shot.toml";
+pub const TRAINING_LOG_FILE: &'static str = "training_log.csv";
+
+//shot.toml の内容。書かれていない項目は既定値になる
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    //敵の出現パターンのファイル。なければ ./enemy_appearance.pat、それもなければバイナリに埋め込んだものを使う
+    pub pattern_file: Option<String>,
+    //[endless] があればパターンの代わりにエンドレスモードで遊ぶ。中身は難しさの上がり方
+    pub endless: Option<Difficulty>,
+    //学習の記録を書くファイル。拡張子が .jsonl なら JSON Lines になる
+    pub training_log: Option<String>,
+}
+
+impl Config {
+    //ファイルがなければ既定の設定を返す
+    pub fn load(path: &str) -> Result<Config, Box<Error>> {
+        let mut s = String::new();
+        match File::open(path) {
+            Ok(mut file) => file.read_to_string(&mut s)?,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(Box::new(err)),
+        };
+        let config: Config = toml::from_str(&s).map_err(|err| format!("{}: {}", path, err))?;
+        if config.pattern_file.is_some() && config.endless.is_some() {
+            return Err(format!("{}: pattern_file and [endless] cannot be used together", path).into());
+        }
+        Ok(config)
+    }
+
+    pub fn training_log(&self) -> &str {
+        self.training_log.as_ref().map(|path| path.as_str()).unwrap_or(TRAINING_LOG_FILE)
+    }
+
+    pub fn pattern(&self) -> Result<Pattern, PatternError> {
+        match self.endless {
+            Some(ref difficulty) => Ok(endless::pattern(difficulty)),
+            None => Pattern::load(self.pattern_file.as_ref().map(|path| path.as_str())),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use evaluation::{self, EpisodeResult};
+use field::Field;
+use q_learning::{self, QTable};
+use rng::XorShift;
+use training_log::{EpisodeRecord, TrainingLog};
+
+//簡単なものから順にパターンファイルを1行に1つずつ書いたファイル。#から後はコメント
+pub struct Curriculum {
+    pub stages: Vec<String>,
+}
+
+impl Curriculum {
+    pub fn load(path: &str) -> io::Result<Curriculum> {
+        let mut stages = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let stage = line.split('#').next().unwrap().trim();
+            if !stage.is_empty() {
+                stages.push(stage.to_string());
+            }
+        }
+        Ok(Curriculum { stages: stages })
+    }
+}
+
+pub struct Settings {
+    //このクリア率に届いたら次のステージへ進む
+    pub threshold: f64,
+    //何エピソードごとにクリア率を測るか
+    pub check_interval: usize,
+    pub eval_episodes: usize,
+    //1ステージにかける最大エピソード数。届かなくても次へ進む
+    pub max_episodes_per_stage: usize,
+    pub epsilon: f64,
+}
+
+//ステージの切り替わりを curriculum_log.csv に追記する
+struct TransitionLog {
+    file: File,
+}
+
+impl TransitionLog {
+    fn open(path: &str) -> io::Result<TransitionLog> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "episode,stage,pattern,clear_rate,event")?;
+        }
+        Ok(TransitionLog { file: file })
+    }
+
+    fn write(
+        &mut self,
+        episode: usize,
+        stage: usize,
+        pattern: &str,
+        clear_rate: f64,
+        event: &str,
+    ) -> io::Result<()> {
+        println!(
+            "episode {}: stage {} ({}) {} at clear rate {:.3}",
+            episode,
+            stage,
+            pattern,
+            event,
+            clear_rate
+        );
+        writeln!(
+            self.file,
+            "{},{},{},{},{}",
+            episode,
+            stage,
+            pattern,
+            clear_rate,
+            event
+        )?;
+        self.file.flush()
+    }
+}
+
+pub fn run(
+    curriculum: &Curriculum,
+    settings: &Settings,
+    q_table: &mut QTable,
+    rng: &mut XorShift,
+    log: &mut TrainingLog,
+    (width, height): (u32, u32),
+) -> Result<(), Box<Error>> {
+    let mut transitions = TransitionLog::open("curriculum_log.csv")?;
+    let mut episode = 0;
+    for (stage, pattern) in curriculum.stages.iter().enumerate() {
+        let mut field = Field::with_pattern(width, height, pattern)?;
+        let mut eval_field = Field::with_pattern(width, height, pattern)?;
+        transitions.write(episode, stage, pattern, 0.0, "started")?;
+        let mut stage_episodes = 0;
+        loop {
+            let seed = rng.next_u64();
+            let mut episode_rng = XorShift::new(seed);
+            let total_reward =
+                q_learning::train_episode(&mut field, q_table, settings.epsilon, &mut episode_rng);
+            log.write(&EpisodeRecord::from_field(
+                episode,
+                seed,
+                &field,
+                total_reward,
+                settings.epsilon,
+                q_table.len(),
+            ))?;
+            episode += 1;
+            stage_episodes += 1;
+
+            if stage_episodes % settings.check_interval != 0 &&
+                stage_episodes < settings.max_episodes_per_stage
+            {
+                continue;
+            }
+            let mut eval_rng = XorShift::new(episode as u64);
+            let results: Vec<EpisodeResult> = (0..settings.eval_episodes)
+                .map(|_| {
+                    evaluation::run_greedy_episode(&mut eval_field, q_table, &mut eval_rng)
+                })
+                .collect();
+            let clear_rate = results.iter().filter(|r| r.cleared).count() as f64 /
+                results.len().max(1) as f64;
+            if clear_rate >= settings.threshold {
+                transitions.write(episode, stage, pattern, clear_rate, "cleared")?;
+                break;
+            }
+            if stage_episodes >= settings.max_episodes_per_stage {
+                transitions.write(episode, stage, pattern, clear_rate, "gave_up")?;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
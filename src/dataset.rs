@@ -0,0 +1,154 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use field::{Field, EPISODE_TICK_LIMIT, OBSERVATION_SIZE};
+use npy::{self, Array};
+use object::Command;
+use q_learning::State;
+
+pub struct Transition {
+    pub episode: usize,
+    pub state: State,
+    pub observation: [f32; OBSERVATION_SIZE],
+    pub command: Command,
+    pub reward: f64,
+    pub next_state: State,
+    pub next_observation: [f32; OBSERVATION_SIZE],
+    pub done: bool,
+}
+
+pub fn command_index(command: Command) -> usize {
+    Command::iterator().position(|&cmd| cmd == command).unwrap()
+}
+
+//エージェントでも人間でも、field.step(cmd) の代わりに recorder.step(&mut field, cmd) を呼べば記録される
+pub struct Recorder {
+    pub transitions: Vec<Transition>,
+    episode: usize,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder {
+            transitions: Vec::new(),
+            episode: 0,
+        }
+    }
+
+    //エピソードの途中でFieldをリセットしたとき、続きを別のエピソードとして記録する
+    pub fn new_episode(&mut self) {
+        if let Some(last) = self.transitions.last() {
+            if last.episode == self.episode {
+                self.episode += 1;
+            }
+        }
+    }
+
+    pub fn step(&mut self, field: &mut Field, command: Command) -> f64 {
+        let state = field.get_hash();
+        let observation = field.observation();
+        let reward = field.step(command);
+        let done = field.game_end || field.tick >= EPISODE_TICK_LIMIT;
+        self.transitions.push(Transition {
+            episode: self.episode,
+            state: state,
+            observation: observation,
+            command: command,
+            reward: reward,
+            next_state: field.get_hash(),
+            next_observation: field.observation(),
+            done: done,
+        });
+        if done {
+            self.episode += 1;
+        }
+        reward
+    }
+
+    //拡張子で形式を選ぶ。.csv と .npz はそのファイルに、それ以外はディレクトリに配列ごとの.npyを書く
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => self.write_csv(path),
+            Some("npz") => npy::write_npz(path, &self.arrays()),
+            _ => {
+                fs::create_dir_all(path)?;
+                for (name, array) in self.arrays() {
+                    let file = Path::new(path).join(format!("{}.npy", name));
+                    npy::write_npy(&file.to_string_lossy(), &array)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn write_csv(&self, path: &str) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let columns = |prefix: &str| {
+            (0..OBSERVATION_SIZE)
+                .map(|i| format!("{}{}", prefix, i))
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        writeln!(
+            file,
+            "episode,state,{},command,command_name,reward,next_state,{},done",
+            columns("obs_"),
+            columns("next_obs_")
+        )?;
+        let values = |observation: &[f32]| {
+            observation
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        for t in self.transitions.iter() {
+            writeln!(
+                file,
+                "{},{},{},{},{:?},{},{},{},{}",
+                t.episode,
+                t.state,
+                values(&t.observation),
+                command_index(t.command),
+                t.command,
+                t.reward,
+                t.next_state,
+                values(&t.next_observation),
+                t.done as u8
+            )?;
+        }
+        file.flush()
+    }
+
+    fn arrays(&self) -> Vec<(&'static str, Array)> {
+        let n = self.transitions.len();
+        let mut observations = Vec::with_capacity(n * OBSERVATION_SIZE);
+        let mut next_observations = Vec::with_capacity(n * OBSERVATION_SIZE);
+        for t in self.transitions.iter() {
+            observations.extend_from_slice(&t.observation);
+            next_observations.extend_from_slice(&t.next_observation);
+        }
+        let episodes: Vec<i64> = self.transitions.iter().map(|t| t.episode as i64).collect();
+        let states: Vec<u64> = self.transitions.iter().map(|t| t.state).collect();
+        let actions: Vec<i64> = self.transitions
+            .iter()
+            .map(|t| command_index(t.command) as i64)
+            .collect();
+        let rewards: Vec<f64> = self.transitions.iter().map(|t| t.reward).collect();
+        let next_states: Vec<u64> = self.transitions.iter().map(|t| t.next_state).collect();
+        let dones: Vec<bool> = self.transitions.iter().map(|t| t.done).collect();
+        vec![
+            ("episodes", Array::i64(&episodes)),
+            ("states", Array::u64(&states)),
+            ("observations", Array::f32(vec![n, OBSERVATION_SIZE], &observations)),
+            ("actions", Array::i64(&actions)),
+            ("rewards", Array::f64(&rewards)),
+            ("next_states", Array::u64(&next_states)),
+            (
+                "next_observations",
+                Array::f32(vec![n, OBSERVATION_SIZE], &next_observations),
+            ),
+            ("dones", Array::bool(&dones)),
+        ]
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use field::{Field, GRID_CELLS};
+use file_reader::{AppearLocation, Pattern, PatternHeader};
+use generator::{self, Distribution};
+use object::Position;
+use rng::XorShift;
+
+//エンドレスモードの難しさの上がり方。ウェーブごとに数と速さが増えていく
+#[derive(Deserialize, Copy, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Difficulty {
+    //ウェーブの間のフレーム数
+    pub wave_interval: usize,
+    pub start_count: f32,
+    //1ウェーブごとに増える敵の数。小数なら何ウェーブかに1体ずつ増える
+    pub count_step: f32,
+    pub max_count: usize,
+    pub start_speed: f32,
+    pub speed_step: f32,
+    pub max_speed: f32,
+    pub distribution: Distribution,
+    //Ringの半径(マス目)
+    pub ring_radius: f32,
+    //指定すると毎回同じウェーブが出る。なければエピソードの乱数から決まる
+    pub seed: Option<u64>,
+}
+
+impl Default for Difficulty {
+    fn default() -> Difficulty {
+        Difficulty {
+            wave_interval: 200,
+            start_count: 2.0,
+            count_step: 0.5,
+            max_count: 12,
+            start_speed: 1.0,
+            speed_step: 0.05,
+            max_speed: 3.0,
+            distribution: Distribution::Edges,
+            ring_radius: GRID_CELLS / 3.0,
+            seed: None,
+        }
+    }
+}
+
+impl Difficulty {
+    //wave番目(0から)のウェーブの敵の数と速さ
+    pub fn at(&self, wave: usize) -> (usize, f32) {
+        let count = (self.start_count + self.count_step * wave as f32) as usize;
+        let speed = self.start_speed + self.speed_step * wave as f32;
+        (count.max(1).min(self.max_count.max(1)), speed.min(self.max_speed))
+    }
+}
+
+//決まった出現がなく、ウェーブを作り続けるパターン
+pub fn pattern(difficulty: &Difficulty) -> Pattern {
+    Pattern {
+        name: "<endless>".to_string(),
+        header: PatternHeader {
+            name: Some("endless".to_string()),
+            ..PatternHeader::default()
+        },
+        locations: Vec::new(),
+        endless: Some(*difficulty),
+    }
+}
+
+//Fieldが出現を使い切るたびに次のウェーブを作る
+pub struct Endless {
+    difficulty: Difficulty,
+    rng: XorShift,
+    pub wave: usize,
+}
+
+impl Endless {
+    pub fn new(difficulty: &Difficulty) -> Endless {
+        let rng = match difficulty.seed {
+            Some(seed) => XorShift::new(seed),
+            None => XorShift::from_time(),
+        };
+        Endless {
+            difficulty: *difficulty,
+            rng: rng,
+            wave: 0,
+        }
+    }
+
+    //seedが指定されていなければ、エピソードの乱数から引き直す
+    pub fn reseed(&mut self, rng: &mut XorShift) {
+        if self.difficulty.seed.is_none() {
+            self.rng = XorShift::new(rng.next_u64());
+        }
+    }
+
+    //前のウェーブからこれだけ経ったら次のウェーブを作る
+    pub fn interval(&self) -> usize {
+        self.difficulty.wave_interval.max(1)
+    }
+
+    //出すときに作るので、playerは今のプレイヤーの位置(マス目)。出てすぐぶつからないよう、そこを避けて置く
+    //dtは最初の敵のdt。Fieldの出現カウンタの今の値を渡すとすぐに出る
+    pub fn next_wave(&mut self, arena: (u32, u32), player: Position, dt: usize) -> Vec<AppearLocation> {
+        let (count, speed) = self.difficulty.at(self.wave);
+        let settings = generator::Settings {
+            waves: 1,
+            per_wave: count,
+            delay: dt,
+            distribution: self.difficulty.distribution,
+            ring_radius: self.difficulty.ring_radius,
+            arena: arena,
+            seed: 0,
+        };
+        self.wave += 1;
+        let mut locations = generator::wave(&settings, player, &mut self.rng);
+        for location in locations.iter_mut() {
+            location.speed = speed;
+        }
+        locations
+    }
+}
+
+//人が遊んだエンドレスモードの結果を endless_log.csv に追記する
+pub struct EndlessLog {
+    file: File,
+}
+
+impl EndlessLog {
+    pub fn open(path: &str) -> io::Result<EndlessLog> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "unix_time,score,ticks,wave,kills,max_chain")?;
+        }
+        Ok(EndlessLog { file: file })
+    }
+
+    //どの回の記録か分かるよう、書いた時刻を先頭に付ける
+    pub fn write(&mut self, field: &Field) -> io::Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        writeln!(
+            self.file,
+            "{},{},{},{},{},{}",
+            now,
+            field.score,
+            field.tick,
+            field.endless_wave().unwrap_or(0),
+            field.kills,
+            field.max_chain
+        )?;
+        self.file.flush()
+    }
+}
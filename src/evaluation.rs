@@ -0,0 +1,149 @@
+use field::{Field, EPISODE_TICK_LIMIT};
+use file_reader::Pattern;
+use q_learning::{self, QTable};
+use perception::StateEncoder;
+use rng::XorShift;
+use stats::Summary;
+
+pub struct EpisodeResult {
+    pub score: u64,
+    pub ticks: u64,
+    pub kills: u64,
+    pub max_chain: u64,
+    pub cleared: bool,
+}
+
+impl EpisodeResult {
+    pub fn from_field(field: &Field) -> EpisodeResult {
+        EpisodeResult {
+            score: field.score,
+            ticks: field.tick,
+            kills: field.kills,
+            max_chain: field.max_chain,
+            cleared: field.game_end && !field.game_over,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub policy: String,
+    pub pattern: String,
+    pub episodes: usize,
+    pub score: Summary,
+    pub ticks: Summary,
+    pub kills: Summary,
+    pub max_chain: Summary,
+    pub clear_rate: f64,
+}
+
+impl Report {
+    pub fn from_results(policy: &str, pattern: &str, results: &[EpisodeResult]) -> Report {
+        let cleared = results.iter().filter(|r| r.cleared).count();
+        Report {
+            policy: policy.to_string(),
+            pattern: pattern.to_string(),
+            episodes: results.len(),
+            score: summarize(results, |r| r.score),
+            ticks: summarize(results, |r| r.ticks),
+            kills: summarize(results, |r| r.kills),
+            max_chain: summarize(results, |r| r.max_chain),
+            clear_rate: if results.is_empty() {
+                0.0
+            } else {
+                cleared as f64 / results.len() as f64
+            },
+        }
+    }
+}
+
+fn summarize<F: Fn(&EpisodeResult) -> u64>(results: &[EpisodeResult], f: F) -> Summary {
+    let samples: Vec<f64> = results.iter().map(|r| f(r) as f64).collect();
+    Summary::from_samples(&samples)
+}
+
+pub fn run_greedy_episode(
+    field: &mut Field,
+    q_table: &QTable,
+    rng: &mut XorShift,
+) -> EpisodeResult {
+    run_greedy_episode_with(field, q_table, StateEncoder::Perception, rng)
+}
+
+pub fn run_greedy_episode_with(
+    field: &mut Field,
+    q_table: &QTable,
+    encoder: StateEncoder,
+    rng: &mut XorShift,
+) -> EpisodeResult {
+    field.reset_with(rng);
+    while !field.game_end && field.tick < EPISODE_TICK_LIMIT {
+        let state = encoder.encode(field);
+        let cmd = q_learning::command_select(q_table, state, 0.0, rng);
+        field.exec_player_cmd(cmd);
+        field.update();
+    }
+    EpisodeResult::from_field(field)
+}
+
+pub fn evaluate(
+    policy: &str,
+    q_table: &QTable,
+    patterns: &[Pattern],
+    episodes: usize,
+    width: u32,
+    height: u32,
+    rng: &mut XorShift,
+) -> Vec<Report> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            let mut field = Field::new(width, height, pattern);
+            let results: Vec<EpisodeResult> = (0..episodes)
+                .map(|_| run_greedy_episode(&mut field, q_table, rng))
+                .collect();
+            Report::from_results(policy, &pattern.name, &results)
+        })
+        .collect()
+}
+
+pub fn print_table(reports: &[Report]) {
+    println!(
+        "{:<20} {:<24} {:>8} {:>10} {:>12} {:>12} {:>12}",
+        "policy",
+        "pattern",
+        "episodes",
+        "metric",
+        "mean",
+        "median",
+        "std"
+    );
+    for report in reports {
+        let rows = [
+            ("score", report.score),
+            ("ticks", report.ticks),
+            ("kills", report.kills),
+            ("max_chain", report.max_chain),
+        ];
+        for &(name, summary) in rows.iter() {
+            println!(
+                "{:<20} {:<24} {:>8} {:>10} {:>12.2} {:>12.2} {:>12.2}",
+                report.policy,
+                report.pattern,
+                report.episodes,
+                name,
+                summary.mean,
+                summary.median,
+                summary.std
+            );
+        }
+        println!(
+            "{:<20} {:<24} {:>8} {:>10} {:>12.3}",
+            report.policy,
+            report.pattern,
+            report.episodes,
+            "clear_rate",
+            report.clear_rate
+        );
+    }
+}
@@ -1,71 +1,80 @@
 use std::hash::{Hash, Hasher};
 use glium::Display;
-use glium::glutin::{EventsLoop, WindowBuilder, ContextBuilder};
 
 use object::*;
 use render::{Color, Render};
-use file_reader::AppearLocation;
+use file_reader::{AppearLocation, Pattern, PatternError, PatternHeader};
+use formation::{Anchor, Formation};
+use endless::Endless;
+use rng::XorShift;
+use training_log::Outcome;
+
+//終わらないエピソードを打ち切るための上限
+pub const EPISODE_TICK_LIMIT: u64 = 100_000;
+pub const OBSERVATION_SIZE: usize = 9;
+pub const PLAYER_START: Position = Position { x: 70.0, y: 70.0 };
+//パターンの座標は画面を縦横それぞれこの数に等分したマス目で書く
+pub const GRID_CELLS: f32 = 20.0;
+
+//パターンのマス目の座標を画面上の座標にする
+pub fn grid_to_arena(Position { x, y }: Position, width: u32, height: u32) -> Position {
+    Position {
+        x: x * (width as f32 / GRID_CELLS),
+        y: y * (height as f32 / GRID_CELLS),
+    }
+}
+
+pub fn arena_to_grid(Position { x, y }: Position, width: u32, height: u32) -> Position {
+    Position {
+        x: x / (width as f32 / GRID_CELLS),
+        y: y / (height as f32 / GRID_CELLS),
+    }
+}
+
+//実際に出現した敵。座標は画面上のもの
+pub struct Spawn {
+    pub tick: u64,
+    pub pos: Position,
+    pub kind: EnemyKind,
+    pub vector: Vector,
+    pub speed: f32,
+}
 
 pub struct Field {
-    display: Display,
-    pub events_loop: EventsLoop,
+    width: u32,
+    height: u32,
     pub player: Player,
     pub bullet_list: Vec<Bullet>,
     pub enemy_list: Vec<Enemy>,
     explosion_list: Vec<Explosion>,
     appear_location_list: Vec<AppearLocation>,
     appearance_counter: usize,
+    pattern: Pattern,
+    //エンドレスのパターンのときだけある
+    endless: Option<Endless>,
+    //record_spawnsを呼んだときだけ記録する
+    spawn_log: Option<Vec<Spawn>>,
     pub score: u64,
     pub reward: f64,
+    pub tick: u64,
+    pub kills: u64,
+    pub max_chain: u64,
     pub game_over: bool,
     pub game_end: bool,
 }
 
 impl Hash for Field {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        let player_pos = self.player.pos;
-        let origin_pos = Position { x: player_pos.x - 3.0 * PLAYER_RADIUS, y: player_pos.y + 3.0 * PLAYER_RADIUS };
-        let mut enemy_bits: u8 = 0;
-        for y in 0..2 {
-            for x in 0..2 {
-                let search = |pos1: Position, pos2: Position| {
-                    (pos1.x - pos2.x).abs() < PLAYER_RADIUS + (PLAYER_RADIUS * 2.0) &&
-                        (pos1.y - pos2.y).abs() < PLAYER_RADIUS + (PLAYER_RADIUS * 2.0) 
-                };
-                if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| search(pos, Position { x: origin_pos.x + x as f32 * 6.0 * PLAYER_RADIUS, y: origin_pos.y - y as f32 * 6.0 * PLAYER_RADIUS }))
-                {
-                    enemy_bits |= 2u8.pow(x + y * 2);
-                }
-            }
-        }
-        if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| pos.x <= player_pos.x && pos.y >= player_pos.y - PLAYER_RADIUS && pos.y <= player_pos.y + PLAYER_RADIUS) {
-            enemy_bits |= 2u8.pow(4);
-        }
-        if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| pos.x >= player_pos.x && pos.y >= player_pos.y - PLAYER_RADIUS && pos.y <= player_pos.y + PLAYER_RADIUS) {
-            enemy_bits |= 2u8.pow(5);
-        }
-        if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| pos.y <= player_pos.y && pos.x >= player_pos.x - PLAYER_RADIUS && pos.x <= player_pos.x + PLAYER_RADIUS) {
-            enemy_bits |= 2u8.pow(6);
-        }
-        if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| pos.y >= player_pos.y && pos.x >= player_pos.x - PLAYER_RADIUS && pos.x <= player_pos.x + PLAYER_RADIUS) {
-            enemy_bits |= 2u8.pow(7);
-        }
-        enemy_bits.hash(state); 
-        
+        self.perception().hash(state);
     }
 }
-    
+
 type GameState = u64;
 
 impl Field {
-    pub fn new(width: u32, height: u32) -> Field {
-        let events_loop = EventsLoop::new();
-        let window = WindowBuilder::new().with_dimensions(width, height);
-        let context = ContextBuilder::new();
-        let display = Display::new(window, context, &events_loop).unwrap();
-
+    pub fn new(width: u32, height: u32, pattern: &Pattern) -> Field {
         let player = Player {
-            pos: Position { x: 70.0, y: 70.0 },
+            pos: PLAYER_START,
             vector: Vector { x: 0.0, y: 0.0 },
             remain_bullet: MAXIMUM_BULLET,
             state: State::Existing,
@@ -74,27 +83,101 @@ impl Field {
         let mut enemy_list: Vec<Enemy> = Vec::new();
         let mut bullet_list: Vec<Bullet> = Vec::new();
         let mut explosion_list: Vec<Explosion> = Vec::new();
-        let mut appear_location_list = AppearLocation::read_list("enemy_appearance.pat");
+        let mut appear_location_list = pattern.locations.clone();
         appear_location_list.reverse();
         Field {
-            display: display,
-            events_loop: events_loop,
+            width: width,
+            height: height,
             player: player,
             bullet_list: bullet_list,
             enemy_list: enemy_list,
             explosion_list: explosion_list,
             appear_location_list: appear_location_list,
             appearance_counter: 0,
+            pattern: pattern.clone(),
+            endless: pattern.endless.as_ref().map(Endless::new),
+            spawn_log: None,
             score: 0,
             reward: 0.0,
+            tick: 0,
+            kills: 0,
+            max_chain: 0,
             game_over: false,
             game_end: false,
         }
     }
 
+    pub fn with_pattern(width: u32, height: u32, pattern_file: &str) -> Result<Field, PatternError> {
+        Ok(Field::new(width, height, &Pattern::read(pattern_file)?))
+    }
+
+    pub fn pattern_name(&self) -> &str {
+        &self.pattern.name
+    }
+
+    //パターンを差し替えて最初からやり直す
+    pub fn set_pattern(&mut self, pattern: Pattern) {
+        self.pattern = pattern;
+        self.reset();
+    }
+
+    //エンドレスで、これまでに作ったウェーブの数
+    pub fn endless_wave(&self) -> Option<usize> {
+        self.endless.as_ref().map(|endless| endless.wave)
+    }
+
+    //これ以降に出現した敵を記録する。リセットすると記録も空になる
+    pub fn record_spawns(&mut self) {
+        self.spawn_log = Some(Vec::new());
+    }
+
+    //記録した出現をそのまま再現するパターン。記録していなければNone
+    pub fn recorded_pattern(&self) -> Option<Pattern> {
+        let log = match self.spawn_log {
+            Some(ref log) => log,
+            None => return None,
+        };
+        let to_grid = |pos| arena_to_grid(pos, self.width, self.height);
+        let mut previous_tick = 0;
+        let locations = log.iter()
+            .map(|spawn| {
+                let kind = match spawn.kind {
+                    EnemyKind::Path(ref waypoints) => {
+                        EnemyKind::Path(waypoints.iter().map(|&p| to_grid(p)).collect())
+                    }
+                    ref kind => kind.clone(),
+                };
+                //同じフレームに出た敵はdt=0になる
+                let location = AppearLocation {
+                    dt: (spawn.tick - previous_tick) as usize,
+                    pos: to_grid(spawn.pos),
+                    anchor: Anchor::Origin,
+                    formation: Formation::Single,
+                    kind: kind,
+                    vector: spawn.vector,
+                    speed: spawn.speed,
+                    line: 0,
+                };
+                previous_tick = spawn.tick;
+                location
+            })
+            .collect();
+        let source = self.pattern.header.name.as_ref().unwrap_or(&self.pattern.name);
+        Some(Pattern {
+            name: format!("{} (recorded)", self.pattern.name),
+            header: PatternHeader {
+                name: Some(format!("recorded from {}", source)),
+                arena: Some((self.width, self.height)),
+                ..PatternHeader::default()
+            },
+            locations: locations,
+            endless: None,
+        })
+    }
+
     pub fn reset(&mut self) {
         let player = Player {
-            pos: Position { x: 70.0, y: 70.0 },
+            pos: PLAYER_START,
             vector: Vector { x: 0.0, y: 0.0 },
             remain_bullet: MAXIMUM_BULLET,
             state: State::Existing,
@@ -103,7 +186,7 @@ impl Field {
         let enemy_list: Vec<Enemy> = Vec::new();
         let bullet_list: Vec<Bullet> = Vec::new();
         let explosion_list: Vec<Explosion> = Vec::new();
-        let mut appear_location_list = AppearLocation::read_list("enemy_appearance.pat");
+        let mut appear_location_list = self.pattern.locations.clone();
         appear_location_list.reverse();
         self.player = player;
         self.bullet_list.clear();
@@ -111,12 +194,95 @@ impl Field {
         self.explosion_list.clear();
         self.appear_location_list = appear_location_list;
         self.appearance_counter = 0;
+        self.endless = self.pattern.endless.as_ref().map(Endless::new);
+        if let Some(ref mut log) = self.spawn_log {
+            log.clear();
+        }
         self.score = 0;
         self.reward = 0.0;
+        self.tick = 0;
+        self.kills = 0;
+        self.max_chain = 0;
         self.game_over = false;
         self.game_end = false;
     }
 
+    //エピソードの乱数でリセットする。エンドレスのウェーブもこの乱数から決まるので、
+    //同じシードなら同じエピソードになる。エンドレスでなければrngは進めない
+    pub fn reset_with(&mut self, rng: &mut XorShift) {
+        self.reset();
+        if let Some(ref mut endless) = self.endless {
+            endless.reseed(rng);
+        }
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        if self.game_over {
+            Outcome::GameOver
+        } else if self.game_end {
+            Outcome::Cleared
+        } else {
+            Outcome::TimeLimit
+        }
+    }
+
+    //周囲4つの箱と上下左右の直線上に敵がいるかを1ビットずつ表す
+    pub fn perception(&self) -> u8 {
+        let player_pos = self.player.pos;
+        let origin_pos = Position { x: player_pos.x - 3.0 * PLAYER_RADIUS, y: player_pos.y + 3.0 * PLAYER_RADIUS };
+        let mut enemy_bits: u8 = 0;
+        for y in 0..2 {
+            for x in 0..2 {
+                let search = |pos1: Position, pos2: Position| {
+                    (pos1.x - pos2.x).abs() < PLAYER_RADIUS + (PLAYER_RADIUS * 2.0) &&
+                        (pos1.y - pos2.y).abs() < PLAYER_RADIUS + (PLAYER_RADIUS * 2.0) 
+                };
+                if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| search(pos, Position { x: origin_pos.x + x as f32 * 6.0 * PLAYER_RADIUS, y: origin_pos.y - y as f32 * 6.0 * PLAYER_RADIUS }))
+                {
+                    enemy_bits |= 2u8.pow(x + y * 2);
+                }
+            }
+        }
+        if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| pos.x <= player_pos.x && pos.y >= player_pos.y - PLAYER_RADIUS && pos.y <= player_pos.y + PLAYER_RADIUS) {
+            enemy_bits |= 2u8.pow(4);
+        }
+        if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| pos.x >= player_pos.x && pos.y >= player_pos.y - PLAYER_RADIUS && pos.y <= player_pos.y + PLAYER_RADIUS) {
+            enemy_bits |= 2u8.pow(5);
+        }
+        if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| pos.y <= player_pos.y && pos.x >= player_pos.x - PLAYER_RADIUS && pos.x <= player_pos.x + PLAYER_RADIUS) {
+            enemy_bits |= 2u8.pow(6);
+        }
+        if let Some(_) = self.enemy_list.iter().find(|&&Enemy {pos, ..}| pos.y >= player_pos.y && pos.x >= player_pos.x - PLAYER_RADIUS && pos.x <= player_pos.x + PLAYER_RADIUS) {
+            enemy_bits |= 2u8.pow(7);
+        }
+        enemy_bits
+    }
+
+    pub fn nearest_enemy_distance(&self) -> Option<f32> {
+        let player_pos = self.player.pos;
+        self.enemy_list
+            .iter()
+            .map(|enemy| {
+                ((enemy.pos.x - player_pos.x).powf(2.0) + (enemy.pos.y - player_pos.y).powf(2.0))
+                    .sqrt()
+            })
+            .fold(None, |nearest: Option<f32>, d| match nearest {
+                Some(n) if n <= d => Some(n),
+                _ => Some(d),
+            })
+    }
+
+    //ニューラルネット向けの観測ベクトル (知覚ビットと残弾数)
+    pub fn observation(&self) -> [f32; OBSERVATION_SIZE] {
+        let bits = self.perception();
+        let mut observation = [0.0; OBSERVATION_SIZE];
+        for i in 0..8 {
+            observation[i] = ((bits >> i) & 1) as f32;
+        }
+        observation[8] = self.player.remain_bullet as f32 / MAXIMUM_BULLET as f32;
+        observation
+    }
+
     pub fn get_hash(&self) -> u64 {
         use std::collections::hash_map::DefaultHasher;
         let mut hasher = DefaultHasher::new();
@@ -125,6 +291,13 @@ impl Field {
     }
 
     pub fn next_state(&mut self, state: GameState, cmd: Command) -> (GameState, f64) {
+        let reward = self.step(cmd);
+        let state = self.get_hash();
+        (state, reward)
+    }
+
+    //コマンドを1つ実行して1tick進め、報酬を返す
+    pub fn step(&mut self, cmd: Command) -> f64 {
         self.exec_player_cmd(cmd);
         if self.reward as usize != 0 {
         //println!("reward = {}", self.reward);
@@ -138,8 +311,7 @@ impl Field {
         //    self.reward -= 1000000.0;
         //println!("game_over = {}", self.reward);
         }
-        let state = self.get_hash();
-        (state, self.reward)
+        self.reward
     }
 
     pub fn update(&mut self) {
@@ -151,13 +323,31 @@ impl Field {
             bullet.update();
         }
         self.detect_collision();
+        //エンドレスでは出現を使い切っても間隔が空いたら次のウェーブを出すので、やられるまで game_end にならない
+        //ウェーブは出る時に作り、そのときのプレイヤーの位置を使う
+        if self.appear_location_list.is_empty() {
+            let player = arena_to_grid(self.player.pos, self.width, self.height);
+            let counter = self.appearance_counter;
+            if let Some(ref mut endless) = self.endless {
+                if counter >= endless.interval() {
+                    let mut wave = endless.next_wave((self.width, self.height), player, counter);
+                    wave.reverse();
+                    self.appear_location_list = wave;
+                }
+            }
+        }
         if let Err(_) = self.load_enemy_location() {
-            if self.enemy_list.is_empty() {
+            if self.enemy_list.is_empty() && self.endless.is_none() {
                 self.game_end = true;
             }
         }
         self.update_enemy_vector();
         self.appearance_counter += 1;
+        self.tick += 1;
+    }
+
+    fn to_arena(&self, pos: Position) -> Position {
+        grid_to_arena(pos, self.width, self.height)
     }
 
     fn load_enemy_location(&mut self) -> Result<(), &str> {
@@ -168,21 +358,42 @@ impl Field {
         {
             Ok(())
         } else {
-            let (width, height) = self.display.get_framebuffer_dimensions();
-            let Position { x, y } = self.appear_location_list.pop().unwrap().pos;
-            let mut enemy_pos = Position {
-                x: x * (width as f32 / PLAYER_RADIUS),
-                y: y * (height as f32 / PLAYER_RADIUS),
-            };
+            let mut location = self.appear_location_list.pop().unwrap();
             loop {
-                if !((self.player.pos.x - enemy_pos.x).powf(2.0) < PLAYER_RADIUS.powf(2.0) &&
-                         (self.player.pos.y - enemy_pos.y).powf(2.0) < PLAYER_RADIUS.powf(2.0))
-                {
-                    self.enemy_list.push(Enemy {
-                        pos: enemy_pos,
-                        vector: Vector { x: 0.0, y: 0.0 },
-                        state: State::Existing,
-                    });
+                let player = arena_to_grid(self.player.pos, self.width, self.height);
+                let kind = match location.kind {
+                    EnemyKind::Path(ref waypoints) => {
+                        let waypoints = waypoints
+                            .iter()
+                            .map(|&p| self.to_arena(location.anchor.resolve(p, player)));
+                        EnemyKind::Path(waypoints.collect())
+                    }
+                    ref kind => kind.clone(),
+                };
+                //隊形や相対位置は出現するこのときに決まる。プレイヤーに重なる敵は出さない
+                for pos in location.positions(player) {
+                    let enemy_pos = self.to_arena(pos);
+                    if !((self.player.pos.x - enemy_pos.x).powf(2.0) < PLAYER_RADIUS.powf(2.0) &&
+                             (self.player.pos.y - enemy_pos.y).powf(2.0) < PLAYER_RADIUS.powf(2.0))
+                    {
+                        self.enemy_list.push(Enemy {
+                            pos: enemy_pos,
+                            vector: location.vector,
+                            state: State::Existing,
+                            kind: kind.clone(),
+                            speed: location.speed,
+                            waypoint: 0,
+                        });
+                        if let Some(ref mut log) = self.spawn_log {
+                            log.push(Spawn {
+                                tick: self.tick,
+                                pos: enemy_pos,
+                                kind: kind.clone(),
+                                vector: location.vector,
+                                speed: location.speed,
+                            });
+                        }
+                    }
                 }
                 if self.appear_location_list.is_empty() ||
                     self.appear_location_list[self.appear_location_list.len() - 1].dt != 0
@@ -190,27 +401,55 @@ impl Field {
                     self.appearance_counter = 0;
                     break;
                 }
-                let Position { x, y } = self.appear_location_list.pop().unwrap().pos;
-                enemy_pos = Position {
-                    x: x * (width as f32 / PLAYER_RADIUS),
-                    y: y * (height as f32 / PLAYER_RADIUS),
-                };
+                location = self.appear_location_list.pop().unwrap();
             }
             Ok(())
         }
     }
 
     fn update_enemy_vector(&mut self) {
+        //縦横のうち大きい方の成分がspeedになる向き。もう着いていれば止まる
+        let toward = |from: Position, to: Position, speed: f32| {
+            let vec_x = to.x - from.x;
+            let vec_y = to.y - from.y;
+            let max = vec_x.abs().max(vec_y.abs());
+            if max == 0.0 {
+                Vector { x: 0.0, y: 0.0 }
+            } else {
+                Vector {
+                    x: vec_x / max * speed,
+                    y: vec_y / max * speed,
+                }
+            }
+        };
         let player_pos = self.player.pos;
+        let (width, height) = (self.width as f32, self.height as f32);
         for enemy in self.enemy_list.iter_mut() {
             let enemy_pos = enemy.pos;
-            let vec_x = player_pos.x - enemy_pos.x;
-            let vec_y = player_pos.y - enemy_pos.y;
-            let dir = Vector {
-                x: vec_x / vec_x.abs().max(vec_y.abs()),
-                y: vec_y / vec_x.abs().max(vec_y.abs()),
-            };
-            enemy.vector = dir;
+            match enemy.kind {
+                EnemyKind::Chaser => enemy.vector = toward(enemy_pos, player_pos, enemy.speed),
+                EnemyKind::Straight => {
+                    if (enemy_pos.x <= PLAYER_RADIUS && enemy.vector.x < 0.0) ||
+                        (enemy_pos.x >= width - PLAYER_RADIUS && enemy.vector.x > 0.0)
+                    {
+                        enemy.vector.x = -enemy.vector.x;
+                    }
+                    if (enemy_pos.y <= PLAYER_RADIUS && enemy.vector.y < 0.0) ||
+                        (enemy_pos.y >= height - PLAYER_RADIUS && enemy.vector.y > 0.0)
+                    {
+                        enemy.vector.y = -enemy.vector.y;
+                    }
+                }
+                EnemyKind::Path(ref waypoints) => {
+                    let target = waypoints[enemy.waypoint];
+                    if (target.x - enemy_pos.x).abs().max((target.y - enemy_pos.y).abs()) <=
+                        enemy.speed
+                    {
+                        enemy.waypoint = (enemy.waypoint + 1) % waypoints.len();
+                    }
+                    enemy.vector = toward(enemy_pos, waypoints[enemy.waypoint], enemy.speed);
+                }
+            }
         }
     }
 
@@ -257,6 +496,7 @@ impl Field {
                         chain: expl.chain + 1,
                     });
                     self.reward += (KILLING_POINT * (expl.chain + 1)) as f64;
+                    self.max_chain = self.max_chain.max(expl.chain + 1);
                     //println!("chain!");
                 }
             }
@@ -291,6 +531,7 @@ impl Field {
                         chain: 1,
                     });
                     self.reward += KILLING_POINT as f64;
+                    self.max_chain = self.max_chain.max(1);
                 }
             }
         }
@@ -306,8 +547,7 @@ impl Field {
         }
 
         //壁の当たり判定と押し出し処理
-        let (width, height) = self.display.get_framebuffer_dimensions();
-        let (width, height) = (width as f32, height as f32);
+        let (width, height) = (self.width as f32, self.height as f32);
         let caluculate_extrusion = |Position { x, y }| {
             let x = if x - PLAYER_RADIUS < 0.0 {
                 0.0 + PLAYER_RADIUS
@@ -338,9 +578,11 @@ impl Field {
         self.bullet_list.retain(|ref bullet| {
             (bullet.state == State::Existing) && on_field(bullet.pos)
         });
+        let enemy_count = self.enemy_list.len();
         self.enemy_list.retain(
             |ref enemy| enemy.state == State::Existing,
         );
+        self.kills += (enemy_count - self.enemy_list.len()) as u64;
         self.explosion_list.retain(|ref expl| {
             expl.radius <= MAXIMUM_EXPLODE_RADIUS
         });
@@ -443,8 +685,8 @@ impl Field {
         self.player.vector = vec;
     }
 
-    pub fn draw(&self) {
-        let mut render = Render::new(&self.display);
+    pub fn draw(&self, display: &Display) {
+        let mut render = Render::new(display);
         render.clear_color(1.0, 1.0, 1.0, 1.0);
 
         //知覚範囲
@@ -460,8 +702,7 @@ impl Field {
             }
         }
         use std::cmp::max;
-        let (width, height) = self.display.get_framebuffer_dimensions();
-        let r = max(width, height);
+        let r = max(self.width, self.height);
         for x in 0..(r as f32 / PLAYER_RADIUS as f32) as i32 {
                  render.draw_rectangle(
                      Position { x: player_pos.x + x as f32 * 2.0 * PLAYER_RADIUS, y: player_pos.y},
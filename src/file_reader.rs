@@ -1,48 +1,764 @@
 use std::error::Error;
-use std::fs::File;
-use std::io::prelude::*;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
 use std::path::Path;
-use object::Position;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+use endless::Difficulty;
+use formation::{self, Anchor, Formation};
+use object::{EnemyKind, Position, Vector};
 
+//パターンファイルの更新時刻を調べる間隔
+const WATCH_INTERVAL_MS: u64 = 500;
+
+//パスが指定されなかったときに使う、バイナリに埋め込んだパターン
+const EMBEDDED_PATTERN: &'static str = include_str!("enemy_appearance.pat");
+//パスの指定がないとき、作業ディレクトリにあればこちらを使う
+pub const DEFAULT_PATTERN_FILE: &'static str = "enemy_appearance.pat";
+const EMBEDDED_PATTERN_NAME: &'static str = "<embedded>";
+
+//読める書式のうち最新のもの。1は dt,x,y の3列だけ、2からは key=value の列とヘッダが書ける
+pub const PATTERN_VERSION: u32 = 2;
+
+#[derive(Clone)]
 pub struct AppearLocation {
     pub dt: usize,
+    //x, yは20等分したマス目の単位で、anchorから測る
     pub pos: Position,
+    pub anchor: Anchor,
+    pub formation: Formation,
+    pub kind: EnemyKind,
+    //出現時の速度(ピクセル/フレーム)
+    pub vector: Vector,
+    //ChaserとPathが1フレームに進む距離
+    pub speed: f32,
+    //パターンファイルの行番号。ファイルから読んだものでなければ0
+    pub line: usize,
 }
 
 impl AppearLocation {
-    pub fn read_list(list_file_name: &str) -> Vec<AppearLocation> {
-        let path = Path::new(list_file_name);
-        let display = path.display();
-
-        // pathを読み込み専用モードで開く。これは`io::Result<File>`を返す。
-        let mut file = match File::open(&path) {
-            // `io::Error`の`description`メソッドはエラーを説明する文字列を返す。
-            Err(why) => panic!("couldn't open {}: {}", display, Error::description(&why)),
-            Ok(file) => file,
-        };
+    //version 1の行と同じ、プレイヤーを追いかける敵
+    pub fn chaser(dt: usize, pos: Position) -> AppearLocation {
+        AppearLocation {
+            dt: dt,
+            pos: pos,
+            anchor: Anchor::Origin,
+            formation: Formation::Single,
+            kind: EnemyKind::Chaser,
+            vector: Vector { x: 0.0, y: 0.0 },
+            speed: 1.0,
+            line: 0,
+        }
+    }
+
+    //この行で出る敵のマス目での位置。playerは出現するときのプレイヤーの位置(マス目)
+    pub fn positions(&self, player: Position) -> Vec<Position> {
+        let origin = self.anchor.resolve(self.pos, player);
+        self.formation
+            .offsets()
+            .into_iter()
+            .map(|offset| {
+                Position {
+                    x: origin.x + offset.x,
+                    y: origin.y + offset.y,
+                }
+            })
+            .collect()
+    }
+
+    //version 1の dt,x,y だけで書けるか
+    fn is_plain(&self) -> bool {
+        let still = self.vector.x == 0.0 && self.vector.y == 0.0 && self.speed == 1.0;
+        match self.kind {
+            EnemyKind::Chaser => {
+                still && self.anchor == Anchor::Origin && self.formation == Formation::Single
+            }
+            _ => false,
+        }
+    }
+}
+
+//`#! key = value` の行に書くパターン全体の情報
+#[derive(Clone, Debug)]
+pub struct PatternHeader {
+    pub version: u32,
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub difficulty: Option<u32>,
+    //想定している画面の大きさ
+    pub arena: Option<(u32, u32)>,
+}
+
+impl Default for PatternHeader {
+    fn default() -> PatternHeader {
+        PatternHeader {
+            version: 1,
+            name: None,
+            author: None,
+            difficulty: None,
+            arena: None,
+        }
+    }
+}
+
+//読み込み済みの出現パターン。Fieldはこれを持っておき、リセットのたびに複製して使う
+#[derive(Clone)]
+pub struct Pattern {
+    pub name: String,
+    pub header: PatternHeader,
+    pub locations: Vec<AppearLocation>,
+    //あれば、出現を使い切ったあともこの難しさでウェーブを作り続ける
+    pub endless: Option<Difficulty>,
+}
+
+#[derive(Debug)]
+pub enum PatternErrorKind {
+    Io(io::Error),
+    MissingColumn(&'static str),
+    InvalidNumber(&'static str, String),
+    TooManyColumns,
+    UnsupportedVersion(String),
+    UnknownKey(String),
+    InvalidValue(String, String),
+    Conflict(String),
+}
 
-        // ファイルの中身を文字列に読み込む。`io::Result<useize>`を返す。
+//どのファイルの何行何列目で失敗したか。行と列は1始まりで、ファイルを読めなかったときは0
+#[derive(Debug)]
+pub struct PatternError {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub kind: PatternErrorKind,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let PatternErrorKind::Io(ref err) = self.kind {
+            return write!(f, "couldn't read {}: {}", self.file, err);
+        }
+        write!(f, "{}:{}:{}: ", self.file, self.line, self.column)?;
+        match self.kind {
+            PatternErrorKind::Io(_) => unreachable!(),
+            PatternErrorKind::MissingColumn(name) => write!(f, "missing {}", name),
+            PatternErrorKind::InvalidNumber(name, ref value) => {
+                write!(f, "invalid {} `{}`", name, value)
+            }
+            PatternErrorKind::TooManyColumns => {
+                write!(
+                    f,
+                    "expected 3 columns (dt,x,y); extra columns need `#! version = {}`",
+                    PATTERN_VERSION
+                )
+            }
+            PatternErrorKind::UnsupportedVersion(ref version) => {
+                write!(f, "unsupported version `{}` (up to {})", version, PATTERN_VERSION)
+            }
+            PatternErrorKind::UnknownKey(ref key) => write!(f, "unknown key `{}`", key),
+            PatternErrorKind::InvalidValue(ref key, ref value) => {
+                write!(f, "invalid {} `{}`", key, value)
+            }
+            PatternErrorKind::Conflict(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for PatternError {
+    fn description(&self) -> &str {
+        "invalid pattern file"
+    }
+}
+
+impl Pattern {
+    pub fn read(path: &str) -> Result<Pattern, PatternError> {
         let mut s = String::new();
-        let mut lines = Vec::new();
-        match file.read_to_string(&mut s) {
-            Err(why) => panic!("couldn't read {}: {}", display, Error::description(&why)),
-            Ok(_) => lines = s.split('\n').collect(),
-        };
-        let mut appear_location_list = Vec::new();
-        for line in lines {
-            if line.len() < 3 {
-                break;
-            }
-            let line: Vec<&str> = line.split(',').collect();
-            let dt: usize = line[0].parse().unwrap();
-            let x: f32 = line[1].parse().unwrap();
-            let y: f32 = line[2].parse().unwrap();
-            let appear_location = AppearLocation {
-                dt: dt,
-                pos: Position { x: x, y: y },
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut s))
+            .map_err(|err| {
+                PatternError {
+                    file: path.to_string(),
+                    line: 0,
+                    column: 0,
+                    kind: PatternErrorKind::Io(err),
+                }
+            })?;
+        Pattern::parse(&s, path)
+    }
+
+    pub fn embedded() -> Pattern {
+        Pattern::parse(EMBEDDED_PATTERN, EMBEDDED_PATTERN_NAME).expect("embedded pattern is valid")
+    }
+
+    //作業ディレクトリに enemy_appearance.pat があればそのパス
+    pub fn default_file() -> Option<&'static str> {
+        if Path::new(DEFAULT_PATTERN_FILE).exists() {
+            Some(DEFAULT_PATTERN_FILE)
+        } else {
+            None
+        }
+    }
+
+    //パスがあればそのファイルを、なければ ./enemy_appearance.pat を、それもなければ埋め込みのパターンを使う
+    pub fn load(path: Option<&str>) -> Result<Pattern, PatternError> {
+        match path.or(Pattern::default_file()) {
+            Some(path) => Pattern::read(path),
+            None => Ok(Pattern::embedded()),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        write!(file, "{}", self)
+    }
+
+    //1行に dt,x,y を1つずつ。#から後はコメント、空行は読み飛ばす。CRLFでもよい
+    //`#! key = value` の行はヘッダ。version 2からは4列目以降に key=value を並べられる
+    //  #! version = 2
+    //  #! name = Sweep
+    //  #! arena = 400x400
+    //  500,10,1,type=straight,vx=0,vy=2
+    //  0,4,4,type=path,speed=1.5,path=4:4;16:4;16:16
+    //  300,0,0,anchor=player,formation=ring,count=8,radius=5
+    //  0,6,6,anchor=top-right,formation=v,count=5,spacing=3,angle=-135
+    //yは上向き。pathの経由点もanchorから測る
+    pub fn parse(source: &str, name: &str) -> Result<Pattern, PatternError> {
+        let mut header = PatternHeader::default();
+        let mut locations = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let error = |column: usize, kind: PatternErrorKind| {
+                PatternError {
+                    file: name.to_string(),
+                    line: i + 1,
+                    column: column,
+                    kind: kind,
+                }
             };
-            appear_location_list.push(appear_location);
+            let line = line.trim_end_matches('\r');
+            if line.trim_start().starts_with("#!") {
+                let start = line.len() - line.trim_start().len() + 2;
+                parse_header(&line[start..], start, &mut header).map_err(|(column, kind)| {
+                    error(column, kind)
+                })?;
+                continue;
+            }
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut location = parse_location(line, header.version).map_err(|(column, kind)| {
+                error(column, kind)
+            })?;
+            location.line = i + 1;
+            locations.push(location);
+        }
+        Ok(Pattern {
+            name: name.to_string(),
+            header: header,
+            locations: locations,
+            endless: None,
+        })
+    }
+}
+
+//ゲームを動かしたままパターンファイルを書き換えられるよう、更新時刻を見て読み直す
+pub struct PatternWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl PatternWatcher {
+    pub fn new(path: &str) -> PatternWatcher {
+        PatternWatcher {
+            path: path.to_string(),
+            modified: modified(path),
+            last_check: Instant::now(),
+        }
+    }
+
+    //前に調べたときから更新されていれば読み直した結果を返す。毎フレーム呼んでもよい
+    pub fn poll(&mut self) -> Option<Result<Pattern, PatternError>> {
+        if self.last_check.elapsed() < Duration::from_millis(WATCH_INTERVAL_MS) {
+            return None;
+        }
+        self.last_check = Instant::now();
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Pattern::read(&self.path))
+    }
+}
+
+//parseで読み戻せる形で書き出す。version 1で書けるものはversion 1のまま
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let header = &self.header;
+        let version = if self.locations.iter().all(|l| l.is_plain()) {
+            header.version
+        } else {
+            PATTERN_VERSION
+        };
+        if version > 1 {
+            writeln!(f, "#! version = {}", version)?;
+        }
+        if let Some(ref name) = header.name {
+            writeln!(f, "#! name = {}", name)?;
+        }
+        if let Some(ref author) = header.author {
+            writeln!(f, "#! author = {}", author)?;
+        }
+        if let Some(difficulty) = header.difficulty {
+            writeln!(f, "#! difficulty = {}", difficulty)?;
+        }
+        if let Some((width, height)) = header.arena {
+            writeln!(f, "#! arena = {}x{}", width, height)?;
+        }
+        for location in self.locations.iter() {
+            write!(f, "{},{},{}", location.dt, location.pos.x, location.pos.y)?;
+            match location.kind {
+                EnemyKind::Chaser => (),
+                EnemyKind::Straight => write!(f, ",type=straight")?,
+                EnemyKind::Path(_) => write!(f, ",type=path")?,
+            }
+            if location.vector.x != 0.0 {
+                write!(f, ",vx={}", location.vector.x)?;
+            }
+            if location.vector.y != 0.0 {
+                write!(f, ",vy={}", location.vector.y)?;
+            }
+            if location.speed != 1.0 {
+                write!(f, ",speed={}", location.speed)?;
+            }
+            if location.anchor != Anchor::Origin {
+                write!(f, ",anchor={}", location.anchor)?;
+            }
+            match location.formation {
+                Formation::Single => (),
+                Formation::Line { count, spacing, angle } |
+                Formation::V { count, spacing, angle } => {
+                    write!(
+                        f,
+                        ",formation={},count={},spacing={},angle={}",
+                        location.formation.name(),
+                        count,
+                        spacing,
+                        angle
+                    )?
+                }
+                Formation::Ring { count, radius } => {
+                    write!(f, ",formation=ring,count={},radius={}", count, radius)?
+                }
+                Formation::Grid { count, spacing, cols } => {
+                    write!(f, ",formation=grid,count={},spacing={},cols={}", count, spacing, cols)?
+                }
+            }
+            if let EnemyKind::Path(ref waypoints) = location.kind {
+                let waypoints: Vec<String> =
+                    waypoints.iter().map(|p| format!("{}:{}", p.x, p.y)).collect();
+                write!(f, ",path={}", waypoints.join(";"))?;
+            }
+            writeln!(f)?;
         }
-        appear_location_list
+        Ok(())
+    }
+}
+
+//エラーは (列, 種類) で返し、呼び出し側でファイル名と行を付ける
+type ParseResult<T> = Result<T, (usize, PatternErrorKind)>;
+
+//区切り文字で分けた各値と、その値が始まる列(1始まり)
+fn split_columns(s: &str, separator: char) -> Vec<(&str, usize)> {
+    let mut columns = Vec::new();
+    let mut start = 0;
+    for value in s.split(separator) {
+        columns.push((value.trim(), start + 1 + (value.len() - value.trim_start().len())));
+        start += value.len() + 1;
+    }
+    columns
+}
+
+fn invalid(key: &str, value: &str, column: usize) -> (usize, PatternErrorKind) {
+    (column, PatternErrorKind::InvalidValue(key.to_string(), value.to_string()))
+}
+
+fn parse_header(s: &str, offset: usize, header: &mut PatternHeader) -> ParseResult<()> {
+    let column = offset + 1 + (s.len() - s.trim_start().len());
+    let (key, value) = match s.find('=') {
+        Some(eq) => (s[..eq].trim(), s[eq + 1..].trim()),
+        None => return Err(invalid("header", s.trim(), column)),
+    };
+    match key {
+        "version" => {
+            header.version = match value.parse() {
+                Ok(version) if version >= 1 && version <= PATTERN_VERSION => version,
+                _ => {
+                    return Err((column, PatternErrorKind::UnsupportedVersion(value.to_string())))
+                }
+            }
+        }
+        "name" => header.name = Some(value.to_string()),
+        "author" => header.author = Some(value.to_string()),
+        "difficulty" => {
+            header.difficulty = Some(value.parse().map_err(|_| invalid(key, value, column))?)
+        }
+        "arena" => {
+            let mut size = value.splitn(2, 'x').map(|n| n.trim().parse::<u32>());
+            header.arena = match (size.next(), size.next()) {
+                (Some(Ok(width)), Some(Ok(height))) => Some((width, height)),
+                _ => return Err(invalid(key, value, column)),
+            }
+        }
+        _ => return Err((column, PatternErrorKind::UnknownKey(key.to_string()))),
+    }
+    Ok(())
+}
+
+fn parse_location(line: &str, version: u32) -> ParseResult<AppearLocation> {
+    let columns = split_columns(line, ',');
+    let names = ["dt", "x", "y"];
+    if version < 2 && columns.len() > names.len() {
+        return Err((columns[names.len()].1, PatternErrorKind::TooManyColumns));
+    }
+    for (j, &name) in names.iter().enumerate() {
+        match columns.get(j) {
+            Some(&("", column)) => return Err((column, PatternErrorKind::MissingColumn(name))),
+            None => return Err((line.len() + 1, PatternErrorKind::MissingColumn(name))),
+            _ => (),
+        }
+    }
+    let (dt, dt_column) = columns[0];
+    let (x, x_column) = columns[1];
+    let (y, y_column) = columns[2];
+    let dt: usize = dt.parse().map_err(|_| {
+        (dt_column, PatternErrorKind::InvalidNumber("dt", dt.to_string()))
+    })?;
+    let x: f32 = x.parse().map_err(|_| {
+        (x_column, PatternErrorKind::InvalidNumber("x", x.to_string()))
+    })?;
+    let y: f32 = y.parse().map_err(|_| {
+        (y_column, PatternErrorKind::InvalidNumber("y", y.to_string()))
+    })?;
+    let mut location = AppearLocation::chaser(dt, Position { x: x, y: y });
+
+    //4列目以降の key=value
+    let mut kind = None;
+    let mut path = None;
+    let mut formation = None;
+    let mut params = Vec::new();
+    for &(column_value, column) in columns[names.len()..].iter() {
+        let (key, value) = match column_value.find('=') {
+            Some(eq) => (column_value[..eq].trim(), column_value[eq + 1..].trim()),
+            None => return Err(invalid("column", column_value, column)),
+        };
+        let number = || value.parse::<f32>().map_err(|_| invalid(key, value, column));
+        match key {
+            "type" => {
+                kind = Some(match value {
+                    "chaser" => EnemyKind::Chaser,
+                    "straight" => EnemyKind::Straight,
+                    "path" => EnemyKind::Path(Vec::new()),
+                    _ => return Err(invalid(key, value, column)),
+                })
+            }
+            "vx" => location.vector.x = number()?,
+            "vy" => location.vector.y = number()?,
+            "speed" => location.speed = number()?,
+            "path" => {
+                let waypoints = parse_path(value).ok_or_else(|| invalid(key, value, column))?;
+                path = Some((waypoints, column));
+            }
+            "anchor" => location.anchor = value.parse().map_err(|_| invalid(key, value, column))?,
+            "formation" => formation = Some((value, column)),
+            "count" | "spacing" | "radius" | "angle" | "cols" => params.push((key, value, column)),
+            _ => return Err((column, PatternErrorKind::UnknownKey(key.to_string()))),
+        }
+    }
+    location.kind = match (kind, path) {
+        (None, None) => EnemyKind::Chaser,
+        (Some(EnemyKind::Path(_)), None) => {
+            return Err((line.len() + 1, PatternErrorKind::MissingColumn("path")))
+        }
+        (None, Some((waypoints, _))) |
+        (Some(EnemyKind::Path(_)), Some((waypoints, _))) => EnemyKind::Path(waypoints),
+        (Some(_), Some((_, column))) => {
+            let message = "path is only used with type=path".to_string();
+            return Err((column, PatternErrorKind::Conflict(message)));
+        }
+        (Some(kind), None) => kind,
+    };
+    location.formation = parse_formation(formation, params)?;
+    Ok(location)
+}
+
+type Param<'a> = (&'a str, &'a str, usize);
+
+fn take<'a>(params: &mut Vec<Param<'a>>, name: &str) -> Option<Param<'a>> {
+    params.iter().position(|&(key, _, _)| key == name).map(|i| params.remove(i))
+}
+
+fn number<T: FromStr>(param: Option<Param>, default: T) -> ParseResult<T> {
+    match param {
+        Some((key, value, column)) => value.parse().map_err(|_| invalid(key, value, column)),
+        None => Ok(default),
+    }
+}
+
+//countやcolsは1以上
+fn positive(param: Option<Param>, default: usize) -> ParseResult<usize> {
+    match param {
+        Some((key, value, column)) => {
+            match value.parse() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(invalid(key, value, column)),
+            }
+        }
+        None => Ok(default),
+    }
+}
+
+//formation と、その並び方が使う count などの列から並び方を決める
+fn parse_formation(formation: Option<(&str, usize)>, mut params: Vec<Param>) -> ParseResult<Formation> {
+    let formation = match formation {
+        None => Formation::Single,
+        Some(("line", _)) => {
+            Formation::Line {
+                count: positive(take(&mut params, "count"), formation::DEFAULT_COUNT)?,
+                spacing: number(take(&mut params, "spacing"), formation::DEFAULT_SPACING)?,
+                angle: number(take(&mut params, "angle"), 0.0)?,
+            }
+        }
+        Some(("ring", _)) => {
+            Formation::Ring {
+                count: positive(take(&mut params, "count"), formation::DEFAULT_COUNT)?,
+                radius: number(take(&mut params, "radius"), formation::DEFAULT_RADIUS)?,
+            }
+        }
+        Some(("v", _)) => {
+            Formation::V {
+                count: positive(take(&mut params, "count"), formation::DEFAULT_COUNT)?,
+                spacing: number(take(&mut params, "spacing"), formation::DEFAULT_SPACING)?,
+                angle: number(take(&mut params, "angle"), 0.0)?,
+            }
+        }
+        Some(("grid", _)) => {
+            let count = positive(take(&mut params, "count"), formation::DEFAULT_COUNT)?;
+            //省略されたらなるべく正方形に近くなる列数
+            let square = (count as f32).sqrt().ceil() as usize;
+            Formation::Grid {
+                count: count,
+                spacing: number(take(&mut params, "spacing"), formation::DEFAULT_SPACING)?,
+                cols: positive(take(&mut params, "cols"), square)?,
+            }
+        }
+        Some((value, column)) => return Err(invalid("formation", value, column)),
+    };
+    if let Some(&(key, _, column)) = params.first() {
+        let message = format!("{} is not used by formation={}", key, formation.name());
+        return Err((column, PatternErrorKind::Conflict(message)));
+    }
+    Ok(formation)
+}
+
+//x:y を ; で区切って並べた経由点。単位は出現位置と同じマス目
+fn parse_path(s: &str) -> Option<Vec<Position>> {
+    s.split(';')
+        .map(|point| {
+            let mut xy = point.splitn(2, ':').map(|n| n.trim().parse::<f32>());
+            match (xy.next(), xy.next()) {
+                (Some(Ok(x)), Some(Ok(y))) => Some(Position { x: x, y: y }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //失敗した行と列、エラーの種類
+    fn error_at(source: &str) -> (usize, usize, PatternErrorKind) {
+        match Pattern::parse(source, "test.pat") {
+            Ok(_) => panic!("expected an error for {:?}", source),
+            Err(err) => (err.line, err.column, err.kind),
+        }
+    }
+
+    #[test]
+    fn missing_and_invalid_columns() {
+        match error_at("10,5") {
+            (1, 5, PatternErrorKind::MissingColumn("y")) => (),
+            other => panic!("{:?}", other),
+        }
+        match error_at("0,1,1\n10,,3") {
+            (2, 4, PatternErrorKind::MissingColumn("x")) => (),
+            other => panic!("{:?}", other),
+        }
+        match error_at("10,1, abc") {
+            (1, 7, PatternErrorKind::InvalidNumber("y", ref value)) if value == "abc" => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn extra_columns_need_version_2() {
+        match error_at("10,1,2,type=straight") {
+            (1, 8, PatternErrorKind::TooManyColumns) => (),
+            other => panic!("{:?}", other),
+        }
+        let pattern = Pattern::parse("#! version = 2\n10,1,2,type=straight", "test.pat").unwrap();
+        assert_eq!(pattern.locations.len(), 1);
+    }
+
+    #[test]
+    fn header_errors() {
+        match error_at("#! version = 3") {
+            (1, 4, PatternErrorKind::UnsupportedVersion(ref version)) if version == "3" => (),
+            other => panic!("{:?}", other),
+        }
+        match error_at("#! colour = red") {
+            (1, 4, PatternErrorKind::UnknownKey(ref key)) if key == "colour" => (),
+            other => panic!("{:?}", other),
+        }
+        match error_at("  #!arena = 400") {
+            (1, 5, PatternErrorKind::InvalidValue(ref key, ref value))
+                if key == "arena" && value == "400" => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn v2_column_errors() {
+        match error_at("#! version = 2\n0,1,1,colour=red") {
+            (2, 7, PatternErrorKind::UnknownKey(ref key)) if key == "colour" => (),
+            other => panic!("{:?}", other),
+        }
+        match error_at("#! version = 2\n0,1,1,type=boss") {
+            (2, 7, PatternErrorKind::InvalidValue(ref key, ref value))
+                if key == "type" && value == "boss" => (),
+            other => panic!("{:?}", other),
+        }
+        match error_at("#! version = 2\n0,1,1,type=straight,path=1:1") {
+            (2, 21, PatternErrorKind::Conflict(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        match error_at("#! version = 2\n0,1,1,radius=3") {
+            (2, 7, PatternErrorKind::Conflict(_)) => (),
+            other => panic!("{:?}", other),
+        }
+        match error_at("#! version = 2\n0,1,1,type=path") {
+            (2, 16, PatternErrorKind::MissingColumn("path")) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn unreadable_file_has_no_position() {
+        let err = Pattern::read("does/not/exist.pat").err().unwrap();
+        match err.kind {
+            PatternErrorKind::Io(_) => assert_eq!((err.line, err.column), (0, 0)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn crlf_and_comments() {
+        let source = "# comment\r\n#! version = 2\r\n\r\n10,1,2 # trailing\r\n  \r\n\
+                      0,3,4,type=straight,vy=-1\r\n";
+        let pattern = Pattern::parse(source, "test.pat").unwrap();
+        assert_eq!(pattern.header.version, 2);
+        assert_eq!(pattern.locations.len(), 2);
+        assert_eq!(pattern.locations[0].line, 4);
+        assert_eq!(pattern.locations[0].dt, 10);
+        assert_eq!(pattern.locations[1].line, 6);
+        assert_eq!(pattern.locations[1].vector.y, -1.0);
+        //CRLFでも列の位置は変わらない
+        match error_at("0,1,1\r\n10,x,3\r\n") {
+            (2, 4, PatternErrorKind::InvalidNumber("x", _)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn header_keys() {
+        let source = "#! name = Sweep\n#! author = someone\n#! difficulty = 3\n#! arena = 400x300\n0,1,1";
+        let header = Pattern::parse(source, "test.pat").unwrap().header;
+        assert_eq!(header.version, 1);
+        assert_eq!(header.name.as_ref().map(|s| s.as_str()), Some("Sweep"));
+        assert_eq!(header.author.as_ref().map(|s| s.as_str()), Some("someone"));
+        assert_eq!(header.difficulty, Some(3));
+        assert_eq!(header.arena, Some((400, 300)));
+    }
+
+    #[test]
+    fn v2_columns() {
+        let source = "#! version = 2\n\
+                      500,10,1,type=straight,vx=0.5,vy=2\n\
+                      0,4,4,type=path,speed=1.5,path=4:4;16:4;16:16\n\
+                      300,0,0,anchor=player,formation=ring,count=8,radius=5\n\
+                      0,6,6,anchor=top-right,formation=grid,count=5";
+        let locations = Pattern::parse(source, "test.pat").unwrap().locations;
+        match locations[0].kind {
+            EnemyKind::Straight => (),
+            ref other => panic!("{:?}", other),
+        }
+        assert_eq!((locations[0].vector.x, locations[0].vector.y), (0.5, 2.0));
+        match locations[1].kind {
+            EnemyKind::Path(ref waypoints) => {
+                assert_eq!(waypoints.len(), 3);
+                assert_eq!((waypoints[2].x, waypoints[2].y), (16.0, 16.0));
+            }
+            ref other => panic!("{:?}", other),
+        }
+        assert_eq!(locations[1].speed, 1.5);
+        assert_eq!(locations[2].anchor, Anchor::Player);
+        assert_eq!(locations[2].formation, Formation::Ring { count: 8, radius: 5.0 });
+        assert_eq!(locations[3].anchor, Anchor::TopRight);
+        //colsを省略すると正方形に近い列数になる
+        let grid = Formation::Grid {
+            count: 5,
+            spacing: formation::DEFAULT_SPACING,
+            cols: 3,
+        };
+        assert_eq!(locations[3].formation, grid);
+    }
+
+    #[test]
+    fn v2_pattern_round_trips() {
+        let source = "#! version = 2\n\
+                      #! name = Sweep\n\
+                      #! author = someone\n\
+                      #! difficulty = 2\n\
+                      #! arena = 400x400\n\
+                      500,10,1,type=straight,vx=0.5,vy=2\n\
+                      0,4,4,type=path,speed=1.5,path=4:4;16:4;16:16\n\
+                      300,0,0,anchor=player,formation=ring,count=8,radius=5\n\
+                      0,6,6,anchor=top-right,formation=v,count=5,spacing=3,angle=-135\n\
+                      20,2,2,formation=line,count=3,spacing=2,angle=90\n\
+                      20,2,2,formation=grid,count=6,spacing=2,cols=3\n\
+                      40,1,19\n";
+        let pattern = Pattern::parse(source, "test.pat").unwrap();
+        let written = format!("{}", pattern);
+        assert_eq!(written, source);
+        let reread = Pattern::parse(&written, "test.pat").unwrap();
+        assert_eq!(format!("{}", reread), written);
+        assert_eq!(reread.locations.len(), 7);
+    }
+
+    #[test]
+    fn plain_pattern_stays_version_1() {
+        let source = "10,1,2\n20,3,4\n";
+        let pattern = Pattern::parse(source, "test.pat").unwrap();
+        assert_eq!(format!("{}", pattern), source);
     }
 }
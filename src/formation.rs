@@ -0,0 +1,219 @@
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use field::GRID_CELLS;
+use object::Position;
+
+//V字の腕が進行方向の逆向きから開く角度(度)
+const V_ARM_ANGLE: f32 = 35.0;
+//パターンで省略されたときの値。spacingが2マスあれば400x400の画面で隣と重ならない
+pub const DEFAULT_COUNT: usize = 5;
+pub const DEFAULT_SPACING: f32 = 2.0;
+pub const DEFAULT_RADIUS: f32 = 4.0;
+
+//出現位置の x, y をどこから測るか。角からのときは画面の内側に向かって測る
+//yは上向きなので、Origin は左下の角になる
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Anchor {
+    Origin,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    //画面の中心からのずれ
+    Center,
+    //出現するときのプレイヤーの位置からのずれ
+    Player,
+}
+
+impl Anchor {
+    //マス目での位置にする。playerはプレイヤーの今の位置(マス目)
+    pub fn resolve(&self, pos: Position, player: Position) -> Position {
+        let (x, y) = (pos.x, pos.y);
+        let (x, y) = match *self {
+            Anchor::Origin | Anchor::BottomLeft => (x, y),
+            Anchor::TopLeft => (x, GRID_CELLS - y),
+            Anchor::TopRight => (GRID_CELLS - x, GRID_CELLS - y),
+            Anchor::BottomRight => (GRID_CELLS - x, y),
+            Anchor::Center => (GRID_CELLS / 2.0 + x, GRID_CELLS / 2.0 + y),
+            Anchor::Player => (player.x + x, player.y + y),
+        };
+        Position { x: x, y: y }
+    }
+}
+
+impl FromStr for Anchor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Anchor, String> {
+        match s {
+            "origin" => Ok(Anchor::Origin),
+            "top-left" => Ok(Anchor::TopLeft),
+            "top-right" => Ok(Anchor::TopRight),
+            "bottom-left" => Ok(Anchor::BottomLeft),
+            "bottom-right" => Ok(Anchor::BottomRight),
+            "center" => Ok(Anchor::Center),
+            "player" => Ok(Anchor::Player),
+            _ => Err(format!("unknown anchor: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Anchor::Origin => write!(f, "origin"),
+            Anchor::TopLeft => write!(f, "top-left"),
+            Anchor::TopRight => write!(f, "top-right"),
+            Anchor::BottomLeft => write!(f, "bottom-left"),
+            Anchor::BottomRight => write!(f, "bottom-right"),
+            Anchor::Center => write!(f, "center"),
+            Anchor::Player => write!(f, "player"),
+        }
+    }
+}
+
+//1行で何体かをまとめて出す並び方。長さの単位はマス目、角度は度で0が右、90が上
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Formation {
+    Single,
+    //出現位置を中心に angle の向きに並べる
+    Line { count: usize, spacing: f32, angle: f32 },
+    //出現位置を中心にした円周上に等間隔で並べる
+    Ring { count: usize, radius: f32 },
+    //出現位置を先頭にして angle の向きに進む形のV字
+    V { count: usize, spacing: f32, angle: f32 },
+    //出現位置を中心に cols 列で並べる
+    Grid { count: usize, spacing: f32, cols: usize },
+}
+
+impl Formation {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Formation::Single => "single",
+            Formation::Line { .. } => "line",
+            Formation::Ring { .. } => "ring",
+            Formation::V { .. } => "v",
+            Formation::Grid { .. } => "grid",
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        match *self {
+            Formation::Single => 1,
+            Formation::Line { count, .. } |
+            Formation::Ring { count, .. } |
+            Formation::V { count, .. } |
+            Formation::Grid { count, .. } => count,
+        }
+    }
+
+    //出現位置からの各敵のずれ
+    pub fn offsets(&self) -> Vec<Position> {
+        let polar = |distance: f32, degrees: f32| {
+            let radians = degrees * PI / 180.0;
+            Position {
+                x: distance * radians.cos(),
+                y: distance * radians.sin(),
+            }
+        };
+        match *self {
+            Formation::Single => vec![Position { x: 0.0, y: 0.0 }],
+            Formation::Line { count, spacing, angle } => {
+                (0..count)
+                    .map(|i| polar((i as f32 - (count - 1) as f32 / 2.0) * spacing, angle))
+                    .collect()
+            }
+            Formation::Ring { count, radius } => {
+                (0..count)
+                    .map(|i| polar(radius, 360.0 * i as f32 / count as f32))
+                    .collect()
+            }
+            Formation::V { count, spacing, angle } => {
+                //先頭から左右の腕に交互に置く
+                (0..count)
+                    .map(|i| {
+                        let side = if i % 2 == 1 { 1.0 } else { -1.0 };
+                        polar(((i + 1) / 2) as f32 * spacing, angle + 180.0 + side * V_ARM_ANGLE)
+                    })
+                    .collect()
+            }
+            Formation::Grid { count, spacing, cols } => {
+                let rows = (count + cols - 1) / cols;
+                (0..count)
+                    .map(|i| {
+                        Position {
+                            x: ((i % cols) as f32 - (cols - 1) as f32 / 2.0) * spacing,
+                            y: ((i / cols) as f32 - (rows - 1) as f32 / 2.0) * spacing,
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Position, (x, y): (f32, f32)) -> bool {
+        (a.x - x).abs() < 1e-4 && (a.y - y).abs() < 1e-4
+    }
+
+    #[test]
+    fn anchors_measure_from_their_corners() {
+        let pos = Position { x: 1.0, y: 2.0 };
+        let player = Position { x: 5.0, y: 6.0 };
+        let resolve = |anchor: Anchor| anchor.resolve(pos, player);
+        assert!(close(resolve(Anchor::Origin), (1.0, 2.0)));
+        assert!(close(resolve(Anchor::BottomLeft), (1.0, 2.0)));
+        assert!(close(resolve(Anchor::TopLeft), (1.0, 18.0)));
+        assert!(close(resolve(Anchor::TopRight), (19.0, 18.0)));
+        assert!(close(resolve(Anchor::BottomRight), (19.0, 2.0)));
+        assert!(close(resolve(Anchor::Center), (11.0, 12.0)));
+        assert!(close(resolve(Anchor::Player), (6.0, 8.0)));
+    }
+
+    #[test]
+    fn angle_90_points_up() {
+        let line = Formation::Line {
+            count: 3,
+            spacing: 2.0,
+            angle: 90.0,
+        };
+        let offsets = line.offsets();
+        assert!(close(offsets[0], (0.0, -2.0)));
+        assert!(close(offsets[1], (0.0, 0.0)));
+        assert!(close(offsets[2], (0.0, 2.0)));
+        //上に進むV字の腕は先頭より下に伸びる
+        let v = Formation::V {
+            count: 3,
+            spacing: 2.0,
+            angle: 90.0,
+        };
+        let offsets = v.offsets();
+        assert!(close(offsets[0], (0.0, 0.0)));
+        assert!(offsets[1].y < 0.0 && offsets[2].y < 0.0);
+        assert!(offsets[1].x * offsets[2].x < 0.0);
+    }
+
+    #[test]
+    fn ring_and_grid_are_centered() {
+        let ring = Formation::Ring {
+            count: 4,
+            radius: 3.0,
+        };
+        let offsets = ring.offsets();
+        assert!(close(offsets[0], (3.0, 0.0)));
+        assert!(close(offsets[1], (0.0, 3.0)));
+        let grid = Formation::Grid {
+            count: 4,
+            spacing: 2.0,
+            cols: 2,
+        };
+        let offsets = grid.offsets();
+        assert!(close(offsets[0], (-1.0, -1.0)));
+        assert!(close(offsets[3], (1.0, 1.0)));
+    }
+}
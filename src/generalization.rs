@@ -0,0 +1,104 @@
+use std::error::Error;
+use evaluation::{self, Report};
+use field::Field;
+use file_reader::Pattern;
+use q_learning::{self, QTable};
+use rng::XorShift;
+use training_log::{EpisodeRecord, TrainingLog};
+
+//エピソードごとに学習用パターンから1つ選んで学習する
+pub fn train(
+    q_table: &mut QTable,
+    train_patterns: &[Pattern],
+    episodes: usize,
+    epsilon: f64,
+    rng: &mut XorShift,
+    log: &mut TrainingLog,
+    (width, height): (u32, u32),
+) -> Result<(), Box<Error>> {
+    let mut fields: Vec<Field> = train_patterns
+        .iter()
+        .map(|pattern| Field::new(width, height, pattern))
+        .collect();
+    for episode in 0..episodes {
+        let field = &mut fields[rng.gen_range(0, train_patterns.len())];
+        let seed = rng.next_u64();
+        let mut episode_rng = XorShift::new(seed);
+        let total_reward = q_learning::train_episode(field, q_table, epsilon, &mut episode_rng);
+        log.write(&EpisodeRecord::from_field(
+            episode,
+            seed,
+            field,
+            total_reward,
+            epsilon,
+            q_table.len(),
+        ))?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+pub struct Comparison {
+    pub in_distribution: Vec<Report>,
+    pub out_of_distribution: Vec<Report>,
+    pub in_distribution_score: f64,
+    pub out_of_distribution_score: f64,
+    pub in_distribution_clear_rate: f64,
+    pub out_of_distribution_clear_rate: f64,
+}
+
+//パターンごとの平均をさらにパターン間で平均する
+fn average<F: Fn(&Report) -> f64>(reports: &[Report], f: F) -> f64 {
+    if reports.is_empty() {
+        0.0
+    } else {
+        reports.iter().map(|r| f(r)).sum::<f64>() / reports.len() as f64
+    }
+}
+
+pub fn compare(
+    q_table: &QTable,
+    train_patterns: &[Pattern],
+    test_patterns: &[Pattern],
+    episodes: usize,
+    (width, height): (u32, u32),
+    rng: &mut XorShift,
+) -> Comparison {
+    let in_distribution =
+        evaluation::evaluate("train", q_table, train_patterns, episodes, width, height, rng);
+    let out_of_distribution =
+        evaluation::evaluate("test", q_table, test_patterns, episodes, width, height, rng);
+    Comparison {
+        in_distribution_score: average(&in_distribution, |r| r.score.mean),
+        out_of_distribution_score: average(&out_of_distribution, |r| r.score.mean),
+        in_distribution_clear_rate: average(&in_distribution, |r| r.clear_rate),
+        out_of_distribution_clear_rate: average(&out_of_distribution, |r| r.clear_rate),
+        in_distribution: in_distribution,
+        out_of_distribution: out_of_distribution,
+    }
+}
+
+pub fn print_comparison(comparison: &Comparison) {
+    evaluation::print_table(&comparison.in_distribution);
+    evaluation::print_table(&comparison.out_of_distribution);
+    println!();
+    println!("{:<20} {:>12} {:>12}", "", "score", "clear_rate");
+    println!(
+        "{:<20} {:>12.2} {:>12.3}",
+        "in-distribution",
+        comparison.in_distribution_score,
+        comparison.in_distribution_clear_rate
+    );
+    println!(
+        "{:<20} {:>12.2} {:>12.3}",
+        "out-of-distribution",
+        comparison.out_of_distribution_score,
+        comparison.out_of_distribution_clear_rate
+    );
+    println!(
+        "{:<20} {:>12.2} {:>12.3}",
+        "gap",
+        comparison.in_distribution_score - comparison.out_of_distribution_score,
+        comparison.in_distribution_clear_rate - comparison.out_of_distribution_clear_rate
+    );
+}
@@ -0,0 +1,186 @@
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use field::{arena_to_grid, grid_to_arena, GRID_CELLS, PLAYER_START};
+use file_reader::{AppearLocation, Pattern, PatternHeader};
+use object::{Position, PLAYER_RADIUS};
+use rng::XorShift;
+
+//画面の端からこれだけ(マス目)内側に出す
+const MARGIN: f32 = 1.0;
+//条件に合う位置が見つかるまで引き直す回数の上限
+const MAX_TRIES: usize = 100;
+//Clustersで1つの塊に入れる敵の数と、塊の中心からのずれの最大(マス目)
+const CLUSTER_SIZE: usize = 4;
+const CLUSTER_SPREAD: f32 = 2.5;
+
+#[derive(Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Distribution {
+    //画面のどこでも
+    Uniform,
+    //画面の四辺のどれか
+    Edges,
+    //プレイヤーを中心にした円周上。generateでは初期位置を中心にする
+    Ring,
+    //ウェーブごとにいくつかの塊
+    Clusters,
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Distribution, String> {
+        match s {
+            "uniform" => Ok(Distribution::Uniform),
+            "edges" => Ok(Distribution::Edges),
+            "ring" => Ok(Distribution::Ring),
+            "clusters" => Ok(Distribution::Clusters),
+            _ => Err(format!("unknown distribution: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Distribution::Uniform => write!(f, "uniform"),
+            Distribution::Edges => write!(f, "edges"),
+            Distribution::Ring => write!(f, "ring"),
+            Distribution::Clusters => write!(f, "clusters"),
+        }
+    }
+}
+
+pub struct Settings {
+    pub waves: usize,
+    pub per_wave: usize,
+    //ウェーブの間のフレーム数
+    pub delay: usize,
+    pub distribution: Distribution,
+    //Ringの半径(マス目)
+    pub ring_radius: f32,
+    pub arena: (u32, u32),
+    pub seed: u64,
+}
+
+//読みやすいようにマス目の0.1単位に丸める
+fn round(v: f32) -> f32 {
+    (v * 10.0).round() / 10.0
+}
+
+fn uniform(rng: &mut XorShift, low: f32, high: f32) -> f32 {
+    low + (high - low) * rng.next_f64() as f32
+}
+
+fn inside(pos: Position) -> bool {
+    MARGIN <= pos.x && pos.x <= GRID_CELLS - MARGIN && MARGIN <= pos.y &&
+        pos.y <= GRID_CELLS - MARGIN
+}
+
+pub fn generate(settings: &Settings) -> Pattern {
+    let (width, height) = settings.arena;
+    let mut rng = XorShift::new(settings.seed);
+    let start = arena_to_grid(PLAYER_START, width, height);
+    let mut locations = Vec::new();
+    for _ in 0..settings.waves {
+        locations.extend(wave(settings, start, &mut rng));
+    }
+
+    Pattern {
+        name: format!("{}-{}", settings.distribution, settings.seed),
+        header: PatternHeader {
+            name: Some(format!(
+                "{} waves of {} ({}, seed {})",
+                settings.waves,
+                settings.per_wave,
+                settings.distribution,
+                settings.seed
+            )),
+            arena: Some(settings.arena),
+            ..PatternHeader::default()
+        },
+        locations: locations,
+        endless: None,
+    }
+}
+
+//per_wave体のウェーブを1つ作る。最初の敵は前のウェーブからdelayフレーム後に出る
+//playerはウェーブが出るときのプレイヤーの位置(マス目)で、Ringはここを中心にする
+pub fn wave(settings: &Settings, player: Position, rng: &mut XorShift) -> Vec<AppearLocation> {
+    let (width, height) = settings.arena;
+    //画面内で、プレイヤーにも同じウェーブの敵にも重ならない位置だけを使う
+    let overlaps = |a: Position, b: Position| {
+        let (a, b) = (grid_to_arena(a, width, height), grid_to_arena(b, width, height));
+        (a.x - b.x).abs() < PLAYER_RADIUS * 2.0 && (a.y - b.y).abs() < PLAYER_RADIUS * 2.0
+    };
+    let usable = |pos: Position, placed: &[Position]| {
+        inside(pos) && !overlaps(pos, player) && placed.iter().all(|&p| !overlaps(pos, p))
+    };
+
+    let centers: Vec<Position> = match settings.distribution {
+        Distribution::Clusters => {
+            let count = (settings.per_wave + CLUSTER_SIZE - 1) / CLUSTER_SIZE;
+            (0..count)
+                .map(|_| {
+                    Position {
+                        x: uniform(rng, MARGIN, GRID_CELLS - MARGIN),
+                        y: uniform(rng, MARGIN, GRID_CELLS - MARGIN),
+                    }
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    let mut locations = Vec::new();
+    let mut placed: Vec<Position> = Vec::new();
+    for i in 0..settings.per_wave {
+        let mut candidate = || match settings.distribution {
+            Distribution::Uniform => {
+                Position {
+                    x: uniform(rng, MARGIN, GRID_CELLS - MARGIN),
+                    y: uniform(rng, MARGIN, GRID_CELLS - MARGIN),
+                }
+            }
+            Distribution::Edges => {
+                let along = uniform(rng, MARGIN, GRID_CELLS - MARGIN);
+                match rng.gen_range(0, 4) {
+                    0 => Position { x: MARGIN, y: along },
+                    1 => Position { x: GRID_CELLS - MARGIN, y: along },
+                    2 => Position { x: along, y: MARGIN },
+                    _ => Position { x: along, y: GRID_CELLS - MARGIN },
+                }
+            }
+            Distribution::Ring => {
+                let angle = uniform(rng, 0.0, 2.0 * PI as f32);
+                Position {
+                    x: player.x + settings.ring_radius * angle.cos(),
+                    y: player.y + settings.ring_radius * angle.sin(),
+                }
+            }
+            Distribution::Clusters => {
+                let center = centers[i % centers.len()];
+                Position {
+                    x: center.x + uniform(rng, -CLUSTER_SPREAD, CLUSTER_SPREAD),
+                    y: center.y + uniform(rng, -CLUSTER_SPREAD, CLUSTER_SPREAD),
+                }
+            }
+        };
+        //見つからなければ諦めて最後に引いた位置を画面内に収めて使う
+        let mut pos = candidate();
+        for _ in 1..MAX_TRIES {
+            if usable(pos, &placed) {
+                break;
+            }
+            pos = candidate();
+        }
+        let pos = Position {
+            x: round(pos.x.max(MARGIN).min(GRID_CELLS - MARGIN)),
+            y: round(pos.y.max(MARGIN).min(GRID_CELLS - MARGIN)),
+        };
+        placed.push(pos);
+        let dt = if i == 0 { settings.delay } else { 0 };
+        locations.push(AppearLocation::chaser(dt, pos));
+    }
+    locations
+}
@@ -0,0 +1,61 @@
+use glium::glutin::{ElementState, KeyboardInput, VirtualKeyCode};
+use object::{Command, Direction, ExtendDirection};
+
+//矢印キーで移動、WASDで撃つ
+#[derive(Default)]
+pub struct Keyboard {
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    //押した瞬間に1発だけ撃つ
+    shot: Option<Direction>,
+}
+
+impl Keyboard {
+    pub fn new() -> Keyboard {
+        Keyboard::default()
+    }
+
+    pub fn handle(&mut self, input: KeyboardInput) {
+        let pressed = input.state == ElementState::Pressed;
+        match input.virtual_keycode {
+            Some(VirtualKeyCode::Left) => self.left = pressed,
+            Some(VirtualKeyCode::Right) => self.right = pressed,
+            Some(VirtualKeyCode::Up) => self.up = pressed,
+            Some(VirtualKeyCode::Down) => self.down = pressed,
+            Some(VirtualKeyCode::A) if pressed => self.shot = Some(Direction::Left),
+            Some(VirtualKeyCode::D) if pressed => self.shot = Some(Direction::Right),
+            Some(VirtualKeyCode::W) if pressed => self.shot = Some(Direction::Up),
+            Some(VirtualKeyCode::S) if pressed => self.shot = Some(Direction::Down),
+            _ => (),
+        }
+    }
+
+    pub fn command(&mut self) -> Command {
+        if let Some(dir) = self.shot.take() {
+            return Command::Shot(dir);
+        }
+        let horizontal = match (self.left, self.right) {
+            (true, false) => -1,
+            (false, true) => 1,
+            _ => 0,
+        };
+        let vertical = match (self.down, self.up) {
+            (true, false) => -1,
+            (false, true) => 1,
+            _ => 0,
+        };
+        match (horizontal, vertical) {
+            (-1, 0) => Command::Move(ExtendDirection::Left),
+            (1, 0) => Command::Move(ExtendDirection::Right),
+            (0, 1) => Command::Move(ExtendDirection::Up),
+            (0, -1) => Command::Move(ExtendDirection::Down),
+            (-1, 1) => Command::Move(ExtendDirection::LeftUp),
+            (1, 1) => Command::Move(ExtendDirection::RightUp),
+            (-1, -1) => Command::Move(ExtendDirection::LeftDown),
+            (1, -1) => Command::Move(ExtendDirection::RightDown),
+            _ => Command::Stay,
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use object::Command;
+use q_learning::{QTable, QValue, State};
+
+//dataset/playで書いたCSVから (状態, コマンド) の組を読む
+pub fn read_pairs(path: &str) -> Result<Vec<(State, Command)>, Box<Error>> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let header = match lines.next() {
+        Some(header) => header?,
+        None => return Ok(Vec::new()),
+    };
+    let columns: Vec<&str> = header.trim().split(',').collect();
+    let column = |name: &str| {
+        columns.iter().position(|&c| c == name).ok_or_else(|| {
+            format!("{}: no {} column", path, name)
+        })
+    };
+    let state_column = column("state")?;
+    let command_column = column("command_name")?;
+
+    let mut pairs = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let values: Vec<&str> = line.trim().split(',').collect();
+        if values.len() != columns.len() {
+            return Err(format!("{}:{}: expected {} columns", path, i + 2, columns.len()).into());
+        }
+        pairs.push((values[state_column].parse()?, values[command_column].parse()?));
+    }
+    Ok(pairs)
+}
+
+//状態ごとの多数決。Q値には各コマンドが選ばれた割合、訪問回数には選ばれた回数を入れるので
+//貪欲に選べば一番多く選ばれたコマンドになり、そのままQ学習で続きを学習できる
+pub fn majority_vote(pairs: &[(State, Command)]) -> QTable {
+    let mut counts: HashMap<State, HashMap<Command, u64>> = HashMap::new();
+    for &(state, command) in pairs {
+        *counts.entry(state).or_insert(HashMap::new()).entry(command).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(state, row)| {
+            let total: u64 = row.values().sum();
+            let row = row.into_iter()
+                .map(|(command, count)| {
+                    (
+                        command,
+                        QValue {
+                            value: count as f64 / total as f64,
+                            visits: count,
+                        },
+                    )
+                })
+                .collect();
+            (state, row)
+        })
+        .collect()
+}
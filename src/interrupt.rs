@@ -0,0 +1,20 @@
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ctrlc;
+
+static SIGNAL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+//1回目のSIGINT/SIGTERMでは終了要求を立てるだけにして、2回目で即座に終了する
+pub fn install() {
+    ctrlc::set_handler(|| {
+        if SIGNAL_COUNT.fetch_add(1, Ordering::SeqCst) > 0 {
+            eprintln!("aborted");
+            process::exit(130);
+        }
+        eprintln!("interrupted: finishing current episode (send again to abort)");
+    }).expect("couldn't install signal handler");
+}
+
+pub fn requested() -> bool {
+    SIGNAL_COUNT.load(Ordering::SeqCst) > 0
+}
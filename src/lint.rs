@@ -0,0 +1,288 @@
+use field::{grid_to_arena, EPISODE_TICK_LIMIT, PLAYER_START};
+use file_reader::{AppearLocation, Pattern};
+use formation::Anchor;
+use object::{EnemyKind, Position, PLAYER_RADIUS};
+
+//ヘッダにもオプションにも画面の大きさがないときに使う
+pub const DEFAULT_ARENA: (u32, u32) = (400, 400);
+
+pub struct Settings {
+    //指定があればヘッダのarenaより優先する
+    pub arena: Option<(u32, u32)>,
+    //1フレームに出現してよい敵の数
+    pub max_per_tick: usize,
+}
+
+pub struct Warning {
+    pub line: usize,
+    pub message: String,
+}
+
+pub struct Stats {
+    pub spawns: usize,
+    pub waves: usize,
+    //最後の敵が出現するフレーム
+    pub duration: u64,
+    pub max_per_tick: usize,
+    pub chasers: usize,
+    pub straights: usize,
+    pub paths: usize,
+}
+
+pub struct Lint {
+    pub arena: (u32, u32),
+    pub warnings: Vec<Warning>,
+    pub stats: Stats,
+}
+
+//隊形を展開した1体ぶん。プレイヤー基準のものはプレイヤーからのずれ(マス目)で持つ
+struct Member<'a> {
+    location: &'a AppearLocation,
+    pos: Position,
+    relative: bool,
+}
+
+//プレイヤーと敵の当たり判定と同じ、四角の重なり
+fn overlaps(a: Position, b: Position) -> bool {
+    (a.x - b.x).abs() < PLAYER_RADIUS * 2.0 && (a.y - b.y).abs() < PLAYER_RADIUS * 2.0
+}
+
+//Fieldと同じく、dtは前の出現からのフレーム数で、dt=0の行は直前の行と同時に出る
+fn waves(locations: &[AppearLocation]) -> Vec<(u64, Vec<&AppearLocation>)> {
+    let mut waves: Vec<(u64, Vec<&AppearLocation>)> = Vec::new();
+    let mut tick = 0;
+    for location in locations {
+        tick += location.dt as u64;
+        if location.dt == 0 && !waves.is_empty() {
+            waves.last_mut().unwrap().1.push(location);
+        } else {
+            waves.push((tick, vec![location]));
+        }
+    }
+    waves
+}
+
+pub fn check(pattern: &Pattern, settings: &Settings) -> Lint {
+    let (width, height) = settings.arena.or(pattern.header.arena).unwrap_or(DEFAULT_ARENA);
+    let inside = |pos: Position| {
+        0.0 <= pos.x && pos.x <= width as f32 && 0.0 <= pos.y && pos.y <= height as f32
+    };
+    let mut warnings = Vec::new();
+    let mut warn = |line: usize, message: String| {
+        warnings.push(Warning {
+            line: line,
+            message: message,
+        })
+    };
+
+    //ヘッダの大きさを想定して書かれたパターンを、別の大きさで遊ぶことになる
+    if let Some((header_width, header_height)) = pattern.header.arena {
+        if (header_width, header_height) != (width, height) {
+            warn(
+                0,
+                format!(
+                    "header arena {}x{} differs from the {}x{} arena being linted",
+                    header_width,
+                    header_height,
+                    width,
+                    height
+                ),
+            );
+        }
+    }
+
+    let waves = waves(&pattern.locations);
+    let player = Position { x: 0.0, y: 0.0 };
+    for &(tick, ref wave) in waves.iter() {
+        let members: Vec<Member> = wave.iter()
+            .flat_map(|&location| {
+                location.positions(player).into_iter().map(move |pos| {
+                    Member {
+                        location: location,
+                        pos: pos,
+                        relative: location.anchor == Anchor::Player,
+                    }
+                })
+            })
+            .collect();
+        if members.len() > settings.max_per_tick {
+            warn(
+                wave[0].line,
+                format!(
+                    "{} enemies spawn at tick {} (more than {})",
+                    members.len(),
+                    tick,
+                    settings.max_per_tick
+                ),
+            );
+        }
+        for (i, member) in members.iter().enumerate() {
+            let line = member.location.line;
+            let pos = grid_to_arena(member.pos, width, height);
+            if member.relative {
+                if overlaps(pos, player) {
+                    warn(
+                        line,
+                        format!(
+                            "spawn offset ({:.1}, {:.1}) at tick {} lands on the player",
+                            member.pos.x,
+                            member.pos.y,
+                            tick
+                        ),
+                    );
+                }
+            } else {
+                if !inside(pos) {
+                    warn(
+                        line,
+                        format!(
+                            "spawn ({:.1}, {:.1}) is outside the {}x{} arena",
+                            member.pos.x,
+                            member.pos.y,
+                            width,
+                            height
+                        ),
+                    );
+                }
+                if overlaps(pos, PLAYER_START) {
+                    warn(
+                        line,
+                        format!(
+                            "spawn ({:.1}, {:.1}) at tick {} overlaps the player start ({}, {})",
+                            member.pos.x,
+                            member.pos.y,
+                            tick,
+                            PLAYER_START.x,
+                            PLAYER_START.y
+                        ),
+                    );
+                }
+            }
+            //プレイヤー基準とそうでないものは、プレイヤーの位置がわからないので比べない
+            let overlapped: Vec<usize> = members[..i]
+                .iter()
+                .filter(|other| {
+                    other.relative == member.relative &&
+                        overlaps(pos, grid_to_arena(other.pos, width, height))
+                })
+                .map(|other| other.location.line)
+                .collect();
+            if !overlapped.is_empty() {
+                let others = if overlapped.len() > 1 {
+                    format!(" and {} more", overlapped.len() - 1)
+                } else {
+                    String::new()
+                };
+                warn(
+                    line,
+                    format!(
+                        "spawn ({:.1}, {:.1}) overlaps line {}{} spawning at the same tick",
+                        member.pos.x,
+                        member.pos.y,
+                        overlapped[0],
+                        others
+                    ),
+                );
+            }
+        }
+        for location in wave.iter() {
+            match location.kind {
+                EnemyKind::Straight => {
+                    if location.vector.x == 0.0 && location.vector.y == 0.0 {
+                        warn(location.line, "straight enemy never moves (vx=vy=0)".to_string());
+                    }
+                }
+                EnemyKind::Chaser | EnemyKind::Path(_) => {
+                    if location.speed <= 0.0 {
+                        let message = format!("speed {} means the enemy never moves", location.speed);
+                        warn(location.line, message);
+                    }
+                }
+            }
+            if location.anchor == Anchor::Player {
+                continue;
+            }
+            if let EnemyKind::Path(ref waypoints) = location.kind {
+                for &waypoint in waypoints.iter() {
+                    let waypoint = location.anchor.resolve(waypoint, player);
+                    if !inside(grid_to_arena(waypoint, width, height)) {
+                        warn(
+                            location.line,
+                            format!(
+                                "waypoint ({:.1}, {:.1}) is outside the {}x{} arena",
+                                waypoint.x,
+                                waypoint.y,
+                                width,
+                                height
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    let duration = waves.last().map(|&(tick, _)| tick).unwrap_or(0);
+    if pattern.locations.is_empty() {
+        warn(0, "pattern has no spawns".to_string());
+    } else if duration >= EPISODE_TICK_LIMIT {
+        warn(
+            pattern.locations.last().unwrap().line,
+            format!(
+                "last spawn at tick {} is past the episode limit of {} ticks",
+                duration,
+                EPISODE_TICK_LIMIT
+            ),
+        );
+    }
+
+    //隊形は1行で何体も出るので、数は敵の数で数える
+    let (mut chasers, mut straights, mut paths) = (0, 0, 0);
+    for location in pattern.locations.iter() {
+        let count = location.formation.count();
+        match location.kind {
+            EnemyKind::Chaser => chasers += count,
+            EnemyKind::Straight => straights += count,
+            EnemyKind::Path(_) => paths += count,
+        }
+    }
+    let size = |wave: &[&AppearLocation]| wave.iter().map(|l| l.formation.count()).sum::<usize>();
+    let stats = Stats {
+        spawns: chasers + straights + paths,
+        waves: waves.len(),
+        duration: duration,
+        max_per_tick: waves.iter().map(|&(_, ref wave)| size(wave)).max().unwrap_or(0),
+        chasers: chasers,
+        straights: straights,
+        paths: paths,
+    };
+    Lint {
+        arena: (width, height),
+        warnings: warnings,
+        stats: stats,
+    }
+}
+
+pub fn print(pattern: &Pattern, lint: &Lint) {
+    for warning in lint.warnings.iter() {
+        println!("{}:{}: warning: {}", pattern.name, warning.line, warning.message);
+    }
+    let stats = &lint.stats;
+    println!(
+        "{}: {} spawns in {} waves over {} ticks (arena {}x{})",
+        pattern.name,
+        stats.spawns,
+        stats.waves,
+        stats.duration,
+        lint.arena.0,
+        lint.arena.1
+    );
+    println!(
+        "  at most {} per tick; {} chaser, {} straight, {} path",
+        stats.max_per_tick,
+        stats.chasers,
+        stats.straights,
+        stats.paths
+    );
+    println!("  {} warnings", lint.warnings.len());
+}
@@ -2,133 +2,290 @@
 extern crate glium;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde;
 extern crate bincode;
-extern crate rand;
+extern crate serde_json;
+extern crate ctrlc;
+extern crate toml;
 mod render;
 mod field;
 mod object;
 mod file_reader;
+mod q_learning;
+mod stats;
+mod evaluation;
+mod rng;
+mod training_log;
+mod checkpoint;
+mod interrupt;
+mod parallel;
+mod vec_env;
+mod table_tool;
+mod perception;
+mod table_io;
+mod report;
+mod aliasing;
+mod npy;
+mod dataset;
+mod human;
+mod imitation;
+mod curriculum;
+mod generalization;
+mod sweep;
+mod config;
+mod lint;
+mod generator;
+mod formation;
+mod campaign;
+mod endless;
 
-use std::collections::HashMap;
-use std::{thread, time};
-use std::fs::{File, OpenOptions};
+use std::error::Error;
+use std::{env, process, thread, time};
+use std::fs::{self, File};
+use std::path::Path;
 use std::io::Write;
-use rand::Rng;
+use field::{Field, EPISODE_TICK_LIMIT, OBSERVATION_SIZE};
+use evaluation::{EpisodeResult, Report};
+use q_learning::{QTable, command_select};
+use rng::XorShift;
+use training_log::{EpisodeRecord, TrainingLog};
+use vec_env::VecEnv;
+use table_tool::MergeStrategy;
+use aliasing::AliasingStats;
+use dataset::Recorder;
+use human::Keyboard;
+use curriculum::Curriculum;
 use object::Command;
-use field::Field;
+use checkpoint::{Checkpoint, CHECKPOINT_FILE, BEST_CHECKPOINT_FILE};
+use config::{Config, CONFIG_FILE};
+use file_reader::{Pattern, PatternWatcher};
+use campaign::{Campaign, StageLog, StageResult};
+use endless::EndlessLog;
 
-static ALPHA: f64 = 0.1;
-static DISCOUNT_RATE: f64 = 0.92;
 static EPISILON: f64 = 0.3;
+static CHECKPOINT_INTERVAL: usize = 100;
+static CHECKPOINT_EVAL_EPISODES: usize = 10;
+//ステージをクリアしてから次のステージを始めるまでのフレーム数
+static STAGE_CLEAR_FRAMES: usize = 100;
 
-type State = u64;
-
-fn command_select(
-    q_table: &HashMap<State, HashMap<Command, f64>>,
-    state: State,
-    epsilon: f64,
-) -> Command {
-    let random_num: usize = rand::thread_rng().gen_range(0, 100);
-    if random_num < (epsilon * 100.0) as usize || !q_table.contains_key(&state) ||
-        q_table.get(&state).unwrap().len() == 0
-    {
-        let random_command_num = rand::thread_rng().gen_range(0, 13);
-        *Command::iterator().nth(random_command_num).unwrap()
+//shot.toml で指定されたパターン。指定がなければ ./enemy_appearance.pat か埋め込みのものを使う
+fn configured_pattern() -> Result<Pattern, Box<Error>> {
+    Ok(Config::load(CONFIG_FILE)?.pattern()?)
+}
+
+//shot.toml の training_log で指定されたファイル。指定がなければ training_log.csv
+fn training_log_path() -> Result<String, Box<Error>> {
+    Ok(Config::load(CONFIG_FILE)?.training_log().to_string())
+}
+
+fn open_training_log() -> Result<TrainingLog, Box<Error>> {
+    Ok(TrainingLog::open(&training_log_path()?)?)
+}
+
+//画面を出すコマンド用。watchなら書き換えを見張るWatcherも返す
+fn watched_pattern(
+    path: Option<&String>,
+    watch: bool,
+) -> Result<(Pattern, Option<PatternWatcher>), Box<Error>> {
+    let path = match path {
+        Some(path) => path.clone(),
+        None => {
+            let config = Config::load(CONFIG_FILE)?;
+            let default_file = match config.endless {
+                Some(_) => None,
+                None => Pattern::default_file().map(|path| path.to_string()),
+            };
+            match config.pattern_file.clone().or(default_file) {
+                Some(path) => path,
+                //埋め込みのパターンとエンドレスは見張るファイルがない
+                None if watch => return Err("--watch needs a pattern file".into()),
+                None => return Ok((config.pattern()?, None)),
+            }
+        }
+    };
+    let watcher = if watch {
+        Some(PatternWatcher::new(&path))
     } else {
-        let mut sorted_command_qval: Vec<(&Command, &f64)> =
-            q_table.get(&state).unwrap().iter().collect();
-        sorted_command_qval.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap());
-
-        let cmd = match sorted_command_qval.first() {
-            Some(&(&cmd, q)) => {
-                //if *q as usize != 0 {
-                //    println!("{}", q);
-                //}
-                cmd}, 
-            None => Command::Stay, 
-        };
-        cmd
+        None
+    };
+    Ok((Pattern::read(&path)?, watcher))
+}
+
+//パターンファイルが書き換えられていたら、その内容でステージをやり直す
+//読めなかったときはエラーを表示して今のステージを続ける
+fn reload_if_changed(watcher: &mut Option<PatternWatcher>, field: &mut Field) -> bool {
+    match watcher.as_mut().and_then(|watcher| watcher.poll()) {
+        Some(Ok(pattern)) => {
+            println!("reloaded {}", pattern.name);
+            field.set_pattern(pattern);
+            true
+        }
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            false
+        }
+        None => false,
     }
 }
 
-fn learn() {
-    use glium::glutin;
+//[max_cicle] [seed] [--resume|--fresh]
+fn learn(args: &[String]) -> Result<(), Box<Error>> {
     let width = 400;
     let height = 400;
-    let mut field = Field::new(width, height);
-    let max_cicle = 3;
-    let s_start = field.get_hash();
+    let resume = args.iter().any(|arg| arg == "--resume");
+    let fresh = args.iter().any(|arg| arg == "--fresh");
+    if resume && fresh {
+        return Err("--resume and --fresh cannot be used together".into());
+    }
+    let args: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let pattern = configured_pattern()?;
+    let mut field = Field::new(width, height, &pattern);
+    let max_cicle: usize = match args.get(0) {
+        Some(n) => n.parse()?,
+        None => 3,
+    };
+    //--fresh のときはチェックポイントを無視して q_table.bin から始める
+    let loaded = if fresh { None } else { Checkpoint::load(CHECKPOINT_FILE)? };
+    let mut checkpoint = match loaded {
+        Some(checkpoint) => {
+            //チェックポイントの後に q_table.bin が書かれていれば、再開するとその表を古いもので上書きしてしまう
+            if !resume && checkpoint::is_newer("q_table.bin", CHECKPOINT_FILE)? {
+                return Err(format!(
+                    "q_table.bin is newer than {}; pass --resume to continue from the checkpoint or --fresh to start from q_table.bin",
+                    CHECKPOINT_FILE
+                ).into());
+            }
+            println!("resuming from episode {}", checkpoint.episode);
+            //落ちた学習がチェックポイントの後に書いた行は、やり直す分と重なるので消す
+            let log_path = training_log_path()?;
+            let removed = training_log::truncate_from(&log_path, checkpoint.episode)?;
+            if removed > 0 {
+                println!("removed {} rows past the checkpoint from {}", removed, log_path);
+            }
+            checkpoint
+        }
+        None => {
+            let rng = match args.get(1) {
+                Some(seed) => XorShift::new(seed.parse()?),
+                None => XorShift::from_time(),
+            };
+            let q_table: QTable = q_learning::load_or_empty("q_table.bin")?;
+            Checkpoint::new(q_table, rng)
+        }
+    };
 
-    let mut closed = false;
-    let mut q_table: HashMap<State, HashMap<Command, f64>> = match File::open("q_table.bin") {
-        Ok(file) => bincode::deserialize_from(file).unwrap(),
-        Err(_) => HashMap::new(),
-    };
-    for cicle in 0..max_cicle {
-        field.reset();
-        let mut state: State = field.get_hash();
-        while !(field.game_end || closed) {
-            q_table.entry(state).or_insert(HashMap::new());
-            let command = command_select(&q_table, state, EPISILON);
-            let (next_state, reward) = field.next_state(state, command);
-            let q_value = q_table.get(&state).unwrap().get(&command).unwrap_or(&0.0) +
-                ALPHA *
-                    (reward +
-                         DISCOUNT_RATE *
-                             Command::iterator()
-                                 .map(|command| q_table.get(&state).unwrap().get(&command))
-                                 .map(|value| value.unwrap_or(&0.0))
-                                 .fold(0.0 / 0.0, |m, v| v.max(m)) -
-                         q_table.get(&state).unwrap().get(&command).unwrap_or(&0.0));
-            q_table.get_mut(&state).unwrap().insert(command, q_value);
-            state = next_state;
-            
-            //let ten_millis = time::Duration::from_millis(10);
-            //let now = time::Instant::now();
-            //thread::sleep(ten_millis);
-            //field.draw();
-            //field.events_loop.poll_events(|event| match event {
-            //    glutin::Event::WindowEvent { event, .. } => {
-            //        match event {
-            //            glutin::WindowEvent::Closed => closed = true,
-            //            _ => (),
-            //      }
-            //    }
-            //    _ => (),
-            //});
-        }
-        let mut file = match OpenOptions::new().write(true).append(true).open(
-            "score.csv",
-        ) {
-            Ok(file) => file,
-            Err(_) => File::create("score.csv").unwrap(),
-        };
-        file.write_fmt(format_args!("{},", field.score));
+    let mut log = open_training_log()?;
+    interrupt::install();
+    let mut eval_field = Field::new(width, height, &pattern);
+    while checkpoint.episode < max_cicle && !interrupt::requested() {
+        let seed = checkpoint.rng.next_u64();
+        let mut episode_rng = XorShift::new(seed);
+        let total_reward = q_learning::train_episode(
+            &mut field,
+            &mut checkpoint.q_table,
+            EPISILON,
+            &mut episode_rng,
+        );
+        log.write(&EpisodeRecord::from_field(
+            checkpoint.episode,
+            seed,
+            &field,
+            total_reward,
+            EPISILON,
+            checkpoint.q_table.len(),
+        ))?;
+        checkpoint.episode += 1;
+        if checkpoint.episode % CHECKPOINT_INTERVAL == 0 || checkpoint.episode == max_cicle {
+            save_checkpoint(&mut checkpoint, &mut eval_field)?;
+        }
     }
-    let mut file = File::create("q_table.bin").unwrap();
-    bincode::serialize_into(&mut file, &q_table).unwrap();
+    //q_table.bin を先に書き、チェックポイントの方が新しくなるようにする
+    q_learning::save("q_table.bin", &checkpoint.q_table)?;
+    if interrupt::requested() {
+        checkpoint.save(CHECKPOINT_FILE)?;
+        println!("saved checkpoint at episode {}", checkpoint.episode);
+    } else if Path::new(CHECKPOINT_FILE).exists() {
+        //最後まで終わったチェックポイントは、次の学習で再開しないよう消す
+        fs::remove_file(CHECKPOINT_FILE)?;
+    }
+    Ok(())
 }
 
-fn draw() {
-    use glium::glutin;
-    let mut file = File::open("q_table.bin").unwrap();
+fn learn_parallel(args: &[String]) -> Result<(), Box<Error>> {
+    let threads: usize = match args.get(0) {
+        Some(n) => n.parse()?,
+        None => 4,
+    };
+    let episodes: usize = match args.get(1) {
+        Some(n) => n.parse()?,
+        None => 3,
+    };
+    let base_seed = match args.get(2) {
+        Some(seed) => seed.parse()?,
+        None => XorShift::from_time().next_u64(),
+    };
+
+    let q_table: QTable = q_learning::load_or_empty("q_table.bin")?;
+    let mut log = open_training_log()?;
+    interrupt::install();
+    let q_table = parallel::train(
+        q_table,
+        threads,
+        episodes,
+        EPISILON,
+        base_seed,
+        (400, 400),
+        &configured_pattern()?,
+        &mut log,
+    )?;
+    q_learning::save("q_table.bin", &q_table)?;
+    Ok(())
+}
 
+fn save_checkpoint(checkpoint: &mut Checkpoint, eval_field: &mut Field) -> Result<(), Box<Error>> {
+    //学習用の乱数列を乱さないよう、評価には別の乱数を使う
+    let mut eval_rng = XorShift::new(checkpoint.episode as u64);
+    let results: Vec<EpisodeResult> = (0..CHECKPOINT_EVAL_EPISODES)
+        .map(|_| {
+            evaluation::run_greedy_episode(eval_field, &checkpoint.q_table, &mut eval_rng)
+        })
+        .collect();
+    let score = Report::from_results("checkpoint", eval_field.pattern_name(), &results).score.mean;
+    if checkpoint.record_evaluation(score) {
+        checkpoint.save(BEST_CHECKPOINT_FILE)?;
+    }
+    checkpoint.save(CHECKPOINT_FILE)?;
+    Ok(())
+}
+
+//[--watch] パターンファイルの書き換えを見張り、その内容で最初からやり直す
+fn draw(args: &[String]) -> Result<(), Box<Error>> {
+    use glium::glutin;
     let width = 400;
     let height = 80;
-    let mut field = Field::new(width, height);
-    let mut q_table: HashMap<State, HashMap<Command, f64>> = bincode::deserialize_from(file)
-        .unwrap();
+    let watch = args.iter().any(|arg| arg == "--watch");
+    let mut events_loop = glutin::EventsLoop::new();
+    let display = render::create_display(width, height, &events_loop);
+    let (pattern, mut watcher) = watched_pattern(None, watch)?;
+    let mut field = Field::new(width, height, &pattern);
+    let q_table = q_learning::load("q_table.bin")?;
+    let mut rng = XorShift::from_time();
+    interrupt::install();
     let mut closed = false;
-    while !(closed || field.game_end) {
-        let state = field.get_hash();
-        let cmd = command_select(&q_table, state, 0.1);
-        field.exec_player_cmd(cmd);
-        field.update();
+    //--watch のときは、ステージが終わっても書き換えを待つ
+    while !(closed || interrupt::requested() || (field.game_end && watcher.is_none())) {
+        reload_if_changed(&mut watcher, &mut field);
+        if !field.game_end {
+            let state = field.get_hash();
+            let cmd = command_select(&q_table, state, 0.1, &mut rng);
+            field.exec_player_cmd(cmd);
+            field.update();
+        }
         let ten_millis = time::Duration::from_millis(10);
         let now = time::Instant::now();
         thread::sleep(ten_millis);
-        field.draw();
-        field.events_loop.poll_events(|event| match event {
+        field.draw(&display);
+        events_loop.poll_events(|event| match event {
             glutin::Event::WindowEvent { event, .. } => {
                 match event {
                     glutin::WindowEvent::Closed => closed = true,
@@ -138,8 +295,716 @@ fn draw() {
             _ => (),
         });
     }
+    Ok(())
 }
 
+//eval [episodes] [--q-table <path>]... [pattern...]
+fn evaluate(args: &[String]) -> Result<(), Box<Error>> {
+    let episodes: usize = match args.get(0) {
+        Some(n) => n.parse()?,
+        None => 100,
+    };
+    let mut q_table_files = Vec::new();
+    let mut pattern_files = Vec::new();
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        if arg == "--q-table" {
+            q_table_files.push(rest.next().ok_or("--q-table needs a path")?.clone());
+        } else {
+            pattern_files.push(arg.clone());
+        }
+    }
+    if q_table_files.is_empty() {
+        q_table_files.push("q_table.bin".to_string());
+    }
+    let patterns = if pattern_files.is_empty() {
+        vec![configured_pattern()?]
+    } else {
+        pattern_files.iter().map(|path| Pattern::read(path)).collect::<Result<_, _>>()?
+    };
+    let mut rng = XorShift::from_time();
+    let mut reports = Vec::new();
+    for q_table_file in q_table_files.iter() {
+        let q_table = q_learning::load(q_table_file)?;
+        reports.extend(evaluation::evaluate(
+            q_table_file,
+            &q_table,
+            &patterns,
+            episodes,
+            400,
+            400,
+            &mut rng,
+        ));
+    }
+    evaluation::print_table(&reports);
+    let mut file = File::create("evaluation.json")?;
+    serde_json::to_writer_pretty(&mut file, &reports)?;
+    Ok(())
+}
+
+//ランダムなコマンドでVecEnvを回して、1秒あたりのステップ数を測る
+fn bench(args: &[String]) -> Result<(), Box<Error>> {
+    let count: usize = match args.get(0) {
+        Some(n) => n.parse()?,
+        None => 16,
+    };
+    let steps: usize = match args.get(1) {
+        Some(n) => n.parse()?,
+        None => 10_000,
+    };
+    let mut env = VecEnv::new(count, 400, 400, &configured_pattern()?);
+    let mut rng = XorShift::from_time();
+    env.reset();
+    let start = time::Instant::now();
+    let mut episodes = 0;
+    let mut total_reward = 0.0;
+    for _ in 0..steps {
+        let commands: Vec<Command> = (0..env.len())
+            .map(|_| *Command::iterator().nth(rng.gen_range(0, 13)).unwrap())
+            .collect();
+        let result = env.step(&commands);
+        debug_assert_eq!(result.observations.len(), count * OBSERVATION_SIZE);
+        episodes += result.dones.iter().filter(|&&done| done).count();
+        total_reward += result.rewards.iter().sum::<f64>();
+    }
+    let elapsed = start.elapsed();
+    let seconds = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    println!(
+        "{} envs x {} steps in {:.2}s: {:.0} steps/sec, {} episodes finished, total reward {}",
+        count,
+        steps,
+        seconds,
+        (count * steps) as f64 / seconds,
+        episodes,
+        total_reward
+    );
+    Ok(())
+}
+
+//table merge [--max] <out> <in>... | table diff <a> <b> [top] | table prune <in> <out> <min_visits>
+//table export <in> <out.json|out.csv> | table import <in.json|in.csv> <out>
+fn table(args: &[String]) -> Result<(), Box<Error>> {
+    match args.first().map(|arg| arg.as_str()) {
+        Some("merge") => {
+            let (strategy, args) = match args.get(1).map(|arg| arg.as_str()) {
+                Some("--max") => (MergeStrategy::Max, &args[2..]),
+                _ => (MergeStrategy::VisitWeighted, &args[1..]),
+            };
+            if args.len() < 2 {
+                return Err("usage: table merge [--max] <out> <in>...".into());
+            }
+            let mut tables = Vec::new();
+            for path in args[1..].iter() {
+                tables.push(q_learning::load(path)?);
+            }
+            let merged = table_tool::merge(&tables, strategy);
+            println!("merged {} tables into {} states", tables.len(), merged.len());
+            q_learning::save(&args[0], &merged)?;
+        }
+        Some("diff") if args.len() >= 3 => {
+            let a = q_learning::load(&args[1])?;
+            let b = q_learning::load(&args[2])?;
+            let top = match args.get(3) {
+                Some(n) => n.parse()?,
+                None => 20,
+            };
+            table_tool::print_diff(&table_tool::diff(&a, &b), top);
+        }
+        Some("prune") if args.len() >= 4 => {
+            let mut q_table = q_learning::load(&args[1])?;
+            let removed = table_tool::prune(&mut q_table, args[3].parse()?)?;
+            println!("removed {} states, {} remain", removed, q_table.len());
+            q_learning::save(&args[2], &q_table)?;
+        }
+        Some("export") if args.len() >= 3 => {
+            let q_table = q_learning::load(&args[1])?;
+            table_io::export(&q_table, &args[2])?;
+        }
+        Some("import") if args.len() >= 3 => {
+            let q_table = table_io::import(&args[1])?;
+            println!("imported {} states", q_table.len());
+            q_learning::save(&args[2], &q_table)?;
+        }
+        _ => {
+            return Err(
+                "usage: table merge [--max] <out> <in>... | diff <a> <b> [top] | prune <in> <out> <min_visits> | export <in> <out> | import <in> <out>"
+                    .into(),
+            )
+        }
+    }
+    Ok(())
+}
+
+fn policy_report(args: &[String]) -> Result<(), Box<Error>> {
+    let q_table_file = args.get(0).map(|arg| arg.as_str()).unwrap_or("q_table.bin");
+    let out_file = args.get(1).map(|arg| arg.as_str()).unwrap_or("policy_report.md");
+    let q_table = q_learning::load(q_table_file)?;
+    let mut file = File::create(out_file)?;
+    file.write_all(report::markdown(&q_table).as_bytes())?;
+    Ok(())
+}
+
+//現在の方策でエピソードを回し、状態番号ごとの状況と収益のばらつきを表示する
+fn aliasing_report(args: &[String]) -> Result<(), Box<Error>> {
+    let episodes: usize = match args.get(0) {
+        Some(n) => n.parse()?,
+        None => 100,
+    };
+    let epsilon: f64 = match args.get(1) {
+        Some(e) => e.parse()?,
+        None => EPISILON,
+    };
+    let top: usize = match args.get(2) {
+        Some(n) => n.parse()?,
+        None => 20,
+    };
+    let q_table: QTable = q_learning::load_or_empty("q_table.bin")?;
+    let mut field = Field::new(400, 400, &configured_pattern()?);
+    let mut rng = XorShift::from_time();
+    let mut stats = AliasingStats::new();
+    for _ in 0..episodes {
+        field.reset_with(&mut rng);
+        while !field.game_end && field.tick < EPISODE_TICK_LIMIT {
+            let state = field.get_hash();
+            stats.observe(&field, state);
+            let command = command_select(&q_table, state, epsilon, &mut rng);
+            stats.reward(field.step(command));
+        }
+        stats.end_episode();
+    }
+    stats.print(top);
+    Ok(())
+}
+
+//人間が矢印キーで移動、WASDで撃って遊ぶ。パスを渡すとその遷移を書き出す
+//play [--pattern <file.pat> | --endless] [--watch] [--spawns <out.pat>] [transitions.(csv|npy|npz)]
+//--watch ではパターンファイルを書き換えると、その内容で最初からやり直す
+//--endless ではshot.tomlの[endless]の難しさ(なければ既定値)でウェーブが出続ける
+//終わったら得点と生き残った時間を endless_log.csv に追記する
+fn play(args: &[String]) -> Result<(), Box<Error>> {
+    use glium::glutin;
+    let width = 400;
+    let height = 400;
+    let mut pattern_file = None;
+    let mut endless = false;
+    let mut watch = false;
+    let mut spawns_file = None;
+    let mut dataset_file = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--pattern" => pattern_file = Some(rest.next().ok_or("--pattern needs a path")?),
+            "--endless" => endless = true,
+            "--watch" => watch = true,
+            "--spawns" => spawns_file = Some(rest.next().ok_or("--spawns needs a path")?),
+            _ => dataset_file = Some(arg),
+        }
+    }
+    let mut events_loop = glutin::EventsLoop::new();
+    let display = render::create_display(width, height, &events_loop);
+    let (pattern, mut watcher) = if endless {
+        if pattern_file.is_some() || watch {
+            return Err("--endless cannot be used with --pattern or --watch".into());
+        }
+        let difficulty = Config::load(CONFIG_FILE)?.endless.unwrap_or_default();
+        (endless::pattern(&difficulty), None)
+    } else {
+        watched_pattern(pattern_file, watch)?
+    };
+    let mut field = Field::new(width, height, &pattern);
+    if spawns_file.is_some() {
+        field.record_spawns();
+    }
+    let mut keyboard = Keyboard::new();
+    let mut recorder = Recorder::new();
+    interrupt::install();
+    let mut closed = false;
+    //--watch のときは、ステージが終わっても書き換えを待つ
+    while !(closed || interrupt::requested() || (field.game_end && watcher.is_none())) {
+        events_loop.poll_events(|event| match event {
+            glutin::Event::WindowEvent { event, .. } => {
+                match event {
+                    glutin::WindowEvent::Closed => closed = true,
+                    glutin::WindowEvent::KeyboardInput { input, .. } => keyboard.handle(input),
+                    _ => (),
+                }
+            }
+            _ => (),
+        });
+        if reload_if_changed(&mut watcher, &mut field) {
+            recorder.new_episode();
+        }
+        if !field.game_end {
+            recorder.step(&mut field, keyboard.command());
+        }
+        thread::sleep(time::Duration::from_millis(10));
+        field.draw(&display);
+    }
+    println!("score: {}", field.score);
+    if let Some(wave) = field.endless_wave() {
+        println!("survived {} ticks, reached wave {}", field.tick, wave);
+        EndlessLog::open("endless_log.csv")?.write(&field)?;
+    }
+    if let Some(path) = dataset_file {
+        recorder.save(path)?;
+        println!("wrote {} transitions to {}", recorder.transitions.len(), path);
+    }
+    if let (Some(path), Some(pattern)) = (spawns_file, field.recorded_pattern()) {
+        pattern.save(path)?;
+        println!("wrote {} spawns to {}", pattern.locations.len(), path);
+    }
+    Ok(())
+}
+
+fn log_stage(
+    log: &mut StageLog,
+    episode: usize,
+    campaign: &Campaign,
+    result: &StageResult,
+) -> Result<(), Box<Error>> {
+    println!(
+        "stage {}/{} ({}): {} in {} ticks, score {} (total {})",
+        result.stage + 1,
+        campaign.stages.len(),
+        result.name,
+        result.outcome,
+        result.steps,
+        result.score,
+        result.total_score
+    );
+    log.write(episode, &campaign.name, result)?;
+    Ok(())
+}
+
+//campaign <campaign.toml> [--agent [runs]]
+//--agent がなければキーボードで遊ぶ。どちらもステージごとの結果を campaign_log.csv に追記する
+fn play_campaign(args: &[String]) -> Result<(), Box<Error>> {
+    let campaign = match args.get(0) {
+        Some(path) => Campaign::load(path)?,
+        None => return Err("usage: campaign <campaign.toml> [--agent [runs]]".into()),
+    };
+    let mut log = StageLog::open("campaign_log.csv")?;
+    interrupt::install();
+    match args.get(1).map(|arg| arg.as_str()) {
+        //エージェントはステージごとにエピソードの長さの上限がある
+        Some("--agent") => {
+            let runs: usize = match args.get(2) {
+                Some(n) => n.parse()?,
+                None => 1,
+            };
+            let q_table = q_learning::load("q_table.bin")?;
+            let mut rng = XorShift::from_time();
+            for episode in 0..runs {
+                let mut run = campaign::Run::new(&campaign);
+                while !run.is_over() {
+                    while !run.field.game_end && run.field.tick < EPISODE_TICK_LIMIT {
+                        let cmd = command_select(&q_table, run.field.get_hash(), 0.0, &mut rng);
+                        run.field.exec_player_cmd(cmd);
+                        run.field.update();
+                    }
+                    log_stage(&mut log, episode, &campaign, run.finish_stage())?;
+                }
+                println!("episode {}: total score {}", episode, run.field.score);
+                if interrupt::requested() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+        Some(arg) => Err(format!("unknown option: {}", arg).into()),
+        None => {
+            use glium::glutin;
+            //ステージごとに画面の大きさが違っても、いちばん大きいものに合わせた窓1つで遊ぶ
+            let (width, height) = campaign.max_arena();
+            let mut events_loop = glutin::EventsLoop::new();
+            let display = render::create_display(width, height, &events_loop);
+            let mut keyboard = Keyboard::new();
+            let mut run = campaign::Run::new(&campaign);
+            println!("stage 1/{}: {}", campaign.stages.len(), run.current().name);
+            let mut closed = false;
+            //クリアしたステージを止めて見せておく残りフレーム数
+            let mut clear_frames = 0;
+            while !(closed || run.is_over() || interrupt::requested()) {
+                events_loop.poll_events(|event| match event {
+                    glutin::Event::WindowEvent { event, .. } => {
+                        match event {
+                            glutin::WindowEvent::Closed => closed = true,
+                            glutin::WindowEvent::KeyboardInput { input, .. } => keyboard.handle(input),
+                            _ => (),
+                        }
+                    }
+                    _ => (),
+                });
+                if !run.field.game_end {
+                    run.field.exec_player_cmd(keyboard.command());
+                    run.field.update();
+                    if run.field.game_end && !run.field.game_over {
+                        println!("stage clear!");
+                        clear_frames = STAGE_CLEAR_FRAMES;
+                    }
+                } else if clear_frames > 0 {
+                    clear_frames -= 1;
+                } else {
+                    log_stage(&mut log, 0, &campaign, run.finish_stage())?;
+                    if !run.is_over() {
+                        println!(
+                            "stage {}/{}: {}",
+                            run.stage + 1,
+                            campaign.stages.len(),
+                            run.current().name
+                        );
+                    }
+                }
+                thread::sleep(time::Duration::from_millis(10));
+                run.field.draw(&display);
+            }
+            //窓を閉じたり中断したりしたときも、途中のステージを記録しておく
+            if !run.is_over() {
+                log_stage(&mut log, 0, &campaign, run.finish_stage())?;
+            }
+            println!("score: {}", run.field.score);
+            Ok(())
+        }
+    }
+}
+
+//Qテーブルの方策でエピソードを回して遷移を書き出す
+fn dataset(args: &[String]) -> Result<(), Box<Error>> {
+    if args.len() < 2 {
+        return Err("usage: dataset <episodes> <out.csv|out.npz|out_dir> [epsilon]".into());
+    }
+    let episodes: usize = args[0].parse()?;
+    let epsilon: f64 = match args.get(2) {
+        Some(e) => e.parse()?,
+        None => 0.0,
+    };
+    let q_table: QTable = q_learning::load_or_empty("q_table.bin")?;
+    let mut field = Field::new(400, 400, &configured_pattern()?);
+    let mut rng = XorShift::from_time();
+    let mut recorder = Recorder::new();
+    for _ in 0..episodes {
+        field.reset_with(&mut rng);
+        while !field.game_end && field.tick < EPISODE_TICK_LIMIT {
+            let command = command_select(&q_table, field.get_hash(), epsilon, &mut rng);
+            recorder.step(&mut field, command);
+        }
+    }
+    recorder.save(&args[1])?;
+    println!("wrote {} transitions to {}", recorder.transitions.len(), args[1]);
+    Ok(())
+}
+
+//clone <out> <dataset.csv>... [--finetune <episodes>]
+//記録したプレイを多数決で真似た方策を作り、必要ならQ学習で続きを学習する
+fn behaviour_clone(args: &[String]) -> Result<(), Box<Error>> {
+    let mut finetune_episodes = 0;
+    let mut paths = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if arg == "--finetune" {
+            finetune_episodes = rest.next().ok_or("--finetune needs a number")?.parse()?;
+        } else {
+            paths.push(arg.clone());
+        }
+    }
+    if paths.len() < 2 {
+        return Err("usage: clone <out> <dataset.csv>... [--finetune <episodes>]".into());
+    }
+
+    let mut pairs = Vec::new();
+    for path in paths[1..].iter() {
+        pairs.extend(imitation::read_pairs(path)?);
+    }
+    let mut q_table = imitation::majority_vote(&pairs);
+    println!("cloned {} states from {} pairs", q_table.len(), pairs.len());
+
+    let mut field = Field::new(400, 400, &configured_pattern()?);
+    let mut rng = XorShift::from_time();
+    interrupt::install();
+    for _ in 0..finetune_episodes {
+        if interrupt::requested() {
+            break;
+        }
+        q_learning::train_episode(&mut field, &mut q_table, EPISILON, &mut rng);
+    }
+    q_learning::save(&paths[0], &q_table)?;
+    Ok(())
+}
+
+//curriculum <stages.txt> [threshold] [check_interval] [max_episodes_per_stage]
+fn learn_curriculum(args: &[String]) -> Result<(), Box<Error>> {
+    let curriculum = match args.get(0) {
+        Some(path) => Curriculum::load(path)?,
+        None => {
+            return Err(
+                "usage: curriculum <stages.txt> [threshold] [check_interval] [max_episodes_per_stage]"
+                    .into(),
+            )
+        }
+    };
+    let settings = curriculum::Settings {
+        threshold: match args.get(1) {
+            Some(t) => t.parse()?,
+            None => 0.8,
+        },
+        check_interval: match args.get(2) {
+            Some(n) => {
+                match n.parse()? {
+                    0 => return Err("check_interval must be at least 1".into()),
+                    n => n,
+                }
+            }
+            None => CHECKPOINT_INTERVAL,
+        },
+        eval_episodes: CHECKPOINT_EVAL_EPISODES,
+        max_episodes_per_stage: match args.get(3) {
+            Some(n) => n.parse()?,
+            None => 10_000,
+        },
+        epsilon: EPISILON,
+    };
+    let mut q_table: QTable = q_learning::load_or_empty("q_table.bin")?;
+    let mut rng = XorShift::from_time();
+    let mut log = open_training_log()?;
+    curriculum::run(&curriculum, &settings, &mut q_table, &mut rng, &mut log, (400, 400))?;
+    q_learning::save("q_table.bin", &q_table)?;
+    Ok(())
+}
+
+//generalize --train <a.pat,b.pat> --test <c.pat,d.pat> [--episodes N] [--eval-episodes N]
+//学習用パターンで学習し、学習用と学習に使わなかったパターンでの成績を比べる
+fn generalize(args: &[String]) -> Result<(), Box<Error>> {
+    let mut train_patterns = Vec::new();
+    let mut test_patterns = Vec::new();
+    let mut episodes = 1000;
+    let mut eval_episodes = 100;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let value = rest.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--train" => train_patterns.extend(value.split(',').map(|p| p.to_string())),
+            "--test" => test_patterns.extend(value.split(',').map(|p| p.to_string())),
+            "--episodes" => episodes = value.parse()?,
+            "--eval-episodes" => eval_episodes = value.parse()?,
+            _ => return Err(format!("unknown option: {}", arg).into()),
+        }
+    }
+    if train_patterns.is_empty() || test_patterns.is_empty() {
+        return Err("usage: generalize --train <a.pat,b.pat> --test <c.pat,d.pat> [--episodes N] [--eval-episodes N]".into());
+    }
+
+    let train_patterns: Vec<Pattern> =
+        train_patterns.iter().map(|path| Pattern::read(path)).collect::<Result<_, _>>()?;
+    let test_patterns: Vec<Pattern> =
+        test_patterns.iter().map(|path| Pattern::read(path)).collect::<Result<_, _>>()?;
+    let mut q_table: QTable = q_learning::load_or_empty("q_table.bin")?;
+    let mut rng = XorShift::from_time();
+    let mut log = open_training_log()?;
+    generalization::train(
+        &mut q_table,
+        &train_patterns,
+        episodes,
+        EPISILON,
+        &mut rng,
+        &mut log,
+        (400, 400),
+    )?;
+    q_learning::save("q_table.bin", &q_table)?;
+
+    let comparison = generalization::compare(
+        &q_table,
+        &train_patterns,
+        &test_patterns,
+        eval_episodes,
+        (400, 400),
+        &mut rng,
+    );
+    generalization::print_comparison(&comparison);
+    let mut file = File::create("generalization.json")?;
+    serde_json::to_writer_pretty(&mut file, &comparison)?;
+    Ok(())
+}
+
+//lint [--arena WxH] [--max-per-tick N] <pattern...>
+//警告が1つでもあれば失敗として終わる
+fn lint_patterns(args: &[String]) -> Result<(), Box<Error>> {
+    let mut settings = lint::Settings {
+        arena: None,
+        max_per_tick: 8,
+    };
+    let mut pattern_files = Vec::new();
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--arena" => {
+                let value = rest.next().ok_or("--arena needs a value")?;
+                let mut size = value.splitn(2, 'x');
+                let width = size.next().unwrap().parse()?;
+                let height = size.next().ok_or("--arena needs WxH")?.parse()?;
+                settings.arena = Some((width, height));
+            }
+            "--max-per-tick" => {
+                settings.max_per_tick = rest.next().ok_or("--max-per-tick needs a value")?.parse()?
+            }
+            _ => pattern_files.push(arg.clone()),
+        }
+    }
+    if pattern_files.is_empty() {
+        return Err("usage: lint [--arena WxH] [--max-per-tick N] <pattern...>".into());
+    }
+
+    let mut problems = 0;
+    for pattern_file in pattern_files.iter() {
+        let pattern = match Pattern::read(pattern_file) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                println!("{}", err);
+                problems += 1;
+                continue;
+            }
+        };
+        let result = lint::check(&pattern, &settings);
+        lint::print(&pattern, &result);
+        problems += result.warnings.len();
+    }
+    if problems > 0 {
+        return Err(format!("{} problems found", problems).into());
+    }
+    Ok(())
+}
+
+//generate <out.pat> [--waves N] [--per-wave N] [--delay N]
+//         [--distribution uniform|edges|ring|clusters] [--ring-radius R] [--seed N] [--count N]
+//--count が2以上なら out-0.pat, out-1.pat, ... をシードを1つずつずらして書く
+fn generate_patterns(args: &[String]) -> Result<(), Box<Error>> {
+    let out = match args.get(0) {
+        Some(out) if !out.starts_with("--") => out.clone(),
+        _ => return Err("usage: generate <out.pat> [--waves N] [--per-wave N] [--delay N] [--distribution uniform|edges|ring|clusters] [--ring-radius R] [--seed N] [--count N]".into()),
+    };
+    let mut settings = generator::Settings {
+        waves: 10,
+        per_wave: 5,
+        delay: 300,
+        distribution: generator::Distribution::Edges,
+        ring_radius: 6.0,
+        arena: (400, 400),
+        seed: XorShift::from_time().next_u64(),
+    };
+    let mut count = 1;
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        let value = rest.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--waves" => settings.waves = value.parse()?,
+            "--per-wave" => settings.per_wave = value.parse()?,
+            "--delay" => settings.delay = value.parse()?,
+            "--distribution" => settings.distribution = value.parse()?,
+            "--ring-radius" => settings.ring_radius = value.parse()?,
+            "--seed" => settings.seed = value.parse()?,
+            "--count" => count = value.parse()?,
+            _ => return Err(format!("unknown option: {}", arg).into()),
+        }
+    }
+
+    let base_seed = settings.seed;
+    let stem = out.trim_end_matches(".pat");
+    for i in 0..count {
+        settings.seed = base_seed.wrapping_add(i as u64);
+        let path = if count == 1 {
+            out.clone()
+        } else {
+            format!("{}-{}.pat", stem, i)
+        };
+        generator::generate(&settings).save(&path)?;
+        println!("{} (seed {})", path, settings.seed);
+    }
+    Ok(())
+}
+
+//sweep [--alpha 0.05,0.1 | 0.01..0.5] [--discount ...] [--epsilon const:0.3,linear:1:0.05:500]
+//      [--encoder perception,perception-bullets] [--random N] [--seeds N] [--episodes N]
+//      [--eval-episodes N] [--threads N]
+fn hyperparameter_sweep(args: &[String]) -> Result<(), Box<Error>> {
+    let mut space = sweep::Space {
+        alpha: sweep::Param::Values(vec![q_learning::ALPHA]),
+        discount_rate: sweep::Param::Values(vec![q_learning::DISCOUNT_RATE]),
+        epsilon: vec![q_learning::EpsilonSchedule::Constant(EPISILON)],
+        encoder: vec![perception::StateEncoder::Perception],
+    };
+    let mut settings = sweep::Settings {
+        seeds: 3,
+        episodes: 1000,
+        eval_episodes: 20,
+        threads: 4,
+        size: (400, 400),
+        pattern: configured_pattern()?,
+    };
+    let mut random = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let value = rest.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--alpha" => space.alpha = value.parse()?,
+            "--discount" => space.discount_rate = value.parse()?,
+            "--epsilon" => {
+                space.epsilon = value.split(',').map(|e| e.parse()).collect::<Result<_, _>>()?
+            }
+            "--encoder" => {
+                space.encoder = value.split(',').map(|e| e.parse()).collect::<Result<_, _>>()?
+            }
+            "--random" => random = Some(value.parse()?),
+            "--seeds" => settings.seeds = value.parse()?,
+            "--episodes" => settings.episodes = value.parse()?,
+            "--eval-episodes" => settings.eval_episodes = value.parse()?,
+            "--threads" => settings.threads = value.parse()?,
+            _ => return Err(format!("unknown option: {}", arg).into()),
+        }
+    }
+    let configs = match random {
+        Some(count) => space.random(count, &mut XorShift::from_time()),
+        None => space.grid()?,
+    };
+
+    interrupt::install();
+    let ranked = sweep::run(configs, settings);
+    sweep::print_table(&ranked);
+    let mut file = File::create("sweep.csv")?;
+    writeln!(file, "{}", sweep::CSV_HEADER)?;
+    for (i, r) in ranked.iter().enumerate() {
+        writeln!(file, "{}", sweep::csv_row(i + 1, r))?;
+    }
+    Ok(())
+}
+
+static USAGE: &str = "usage: shot [--watch] | learn | learn-parallel | eval | bench | table | report | aliasing | play | dataset | clone | curriculum | generalize | sweep | lint | generate | campaign";
+
 fn main() {
-    draw();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|arg| arg.as_str()) {
+        Some("learn") => exit_on_error(learn(&args[1..])),
+        Some("learn-parallel") => exit_on_error(learn_parallel(&args[1..])),
+        Some("eval") => exit_on_error(evaluate(&args[1..])),
+        Some("bench") => exit_on_error(bench(&args[1..])),
+        Some("table") => exit_on_error(table(&args[1..])),
+        Some("report") => exit_on_error(policy_report(&args[1..])),
+        Some("aliasing") => exit_on_error(aliasing_report(&args[1..])),
+        Some("play") => exit_on_error(play(&args[1..])),
+        Some("dataset") => exit_on_error(dataset(&args[1..])),
+        Some("clone") => exit_on_error(behaviour_clone(&args[1..])),
+        Some("curriculum") => exit_on_error(learn_curriculum(&args[1..])),
+        Some("generalize") => exit_on_error(generalize(&args[1..])),
+        Some("sweep") => exit_on_error(hyperparameter_sweep(&args[1..])),
+        Some("lint") => exit_on_error(lint_patterns(&args[1..])),
+        Some("generate") => exit_on_error(generate_patterns(&args[1..])),
+        Some("campaign") => exit_on_error(play_campaign(&args[1..])),
+        None | Some("--watch") => exit_on_error(draw(&args)),
+        Some(other) => exit_on_error(Err(format!("unknown command: {}\n{}", other, USAGE).into())),
+    }
+}
+
+fn exit_on_error(result: Result<(), Box<Error>>) {
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
 }
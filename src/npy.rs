@@ -0,0 +1,230 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+//NumPyの.npy/.npzを書き出す。依存を増やさないよう、zipも無圧縮で自前で書く
+pub struct Array {
+    descr: &'static str,
+    shape: Vec<usize>,
+    data: Vec<u8>,
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.push(value as u8);
+    buf.push((value >> 8) as u8);
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    for i in 0..4 {
+        buf.push((value >> (8 * i)) as u8);
+    }
+}
+
+fn push_u64(buf: &mut Vec<u8>, value: u64) {
+    for i in 0..8 {
+        buf.push((value >> (8 * i)) as u8);
+    }
+}
+
+impl Array {
+    pub fn f32(shape: Vec<usize>, values: &[f32]) -> Array {
+        let mut data = Vec::with_capacity(values.len() * 4);
+        for value in values {
+            push_u32(&mut data, value.to_bits());
+        }
+        Array {
+            descr: "<f4",
+            shape: shape,
+            data: data,
+        }
+    }
+
+    pub fn f64(values: &[f64]) -> Array {
+        let mut data = Vec::with_capacity(values.len() * 8);
+        for value in values {
+            push_u64(&mut data, value.to_bits());
+        }
+        Array {
+            descr: "<f8",
+            shape: vec![values.len()],
+            data: data,
+        }
+    }
+
+    pub fn u64(values: &[u64]) -> Array {
+        let mut data = Vec::with_capacity(values.len() * 8);
+        for &value in values {
+            push_u64(&mut data, value);
+        }
+        Array {
+            descr: "<u8",
+            shape: vec![values.len()],
+            data: data,
+        }
+    }
+
+    pub fn i64(values: &[i64]) -> Array {
+        let mut data = Vec::with_capacity(values.len() * 8);
+        for &value in values {
+            push_u64(&mut data, value as u64);
+        }
+        Array {
+            descr: "<i8",
+            shape: vec![values.len()],
+            data: data,
+        }
+    }
+
+    pub fn bool(values: &[bool]) -> Array {
+        Array {
+            descr: "|b1",
+            shape: vec![values.len()],
+            data: values.iter().map(|&value| value as u8).collect(),
+        }
+    }
+
+    //.npy形式 (version 1.0) のバイト列
+    pub fn to_npy(&self) -> Vec<u8> {
+        let shape = match self.shape.len() {
+            1 => format!("({},)", self.shape[0]),
+            _ => {
+                format!(
+                    "({})",
+                    self.shape
+                        .iter()
+                        .map(|n| n.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+            }
+        };
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}",
+            self.descr,
+            shape
+        );
+        //マジック(6)+バージョン(2)+長さ(2)+ヘッダ+改行 が64の倍数になるよう空白で埋める
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+
+        let mut buf = Vec::with_capacity(10 + header.len() + self.data.len());
+        buf.extend_from_slice(b"\x93NUMPY");
+        buf.push(1);
+        buf.push(0);
+        push_u16(&mut buf, header.len() as u16);
+        buf.extend_from_slice(header.as_bytes());
+        buf.extend_from_slice(&self.data);
+        buf
+    }
+}
+
+pub fn write_npy(path: &str, array: &Array) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(&array.to_npy())
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+//zip64には対応していないので、4GBを超える長さや位置はエラーにする
+fn zip_u32(value: usize, what: &str) -> io::Result<u32> {
+    if value > u32::max_value() as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("npz {} ({} bytes) does not fit in a zip without zip64", what, value),
+        ));
+    }
+    Ok(value as u32)
+}
+
+fn zip_u16(value: usize, what: &str) -> io::Result<u16> {
+    if value > u16::max_value() as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("npz {} ({}) does not fit in a zip without zip64", what, value),
+        ));
+    }
+    Ok(value as u16)
+}
+
+//配列ごとに name.npy というエントリを持つ無圧縮のzip
+pub fn write_npz(path: &str, arrays: &[(&str, Array)]) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let mut central_directory = Vec::new();
+    let mut offset = 0usize;
+    let entries = zip_u16(arrays.len(), "entry count")?;
+    //1980-01-01 00:00
+    let (dos_time, dos_date) = (0u16, 0x21u16);
+    for &(name, ref array) in arrays {
+        let name = format!("{}.npy", name);
+        let data = array.to_npy();
+        let crc = crc32(&data);
+        let size = zip_u32(data.len(), "entry size")?;
+        let name_len = zip_u16(name.len(), "entry name length")?;
+        let entry_offset = zip_u32(offset, "entry offset")?;
+
+        let mut local_header = Vec::new();
+        push_u32(&mut local_header, 0x0403_4B50);
+        push_u16(&mut local_header, 20);
+        push_u16(&mut local_header, 0);
+        push_u16(&mut local_header, 0);
+        push_u16(&mut local_header, dos_time);
+        push_u16(&mut local_header, dos_date);
+        push_u32(&mut local_header, crc);
+        push_u32(&mut local_header, size);
+        push_u32(&mut local_header, size);
+        push_u16(&mut local_header, name_len);
+        push_u16(&mut local_header, 0);
+        local_header.extend_from_slice(name.as_bytes());
+
+        push_u32(&mut central_directory, 0x0201_4B50);
+        push_u16(&mut central_directory, 20);
+        push_u16(&mut central_directory, 20);
+        push_u16(&mut central_directory, 0);
+        push_u16(&mut central_directory, 0);
+        push_u16(&mut central_directory, dos_time);
+        push_u16(&mut central_directory, dos_date);
+        push_u32(&mut central_directory, crc);
+        push_u32(&mut central_directory, size);
+        push_u32(&mut central_directory, size);
+        push_u16(&mut central_directory, name_len);
+        push_u16(&mut central_directory, 0);
+        push_u16(&mut central_directory, 0);
+        push_u16(&mut central_directory, 0);
+        push_u16(&mut central_directory, 0);
+        push_u32(&mut central_directory, 0);
+        push_u32(&mut central_directory, entry_offset);
+        central_directory.extend_from_slice(name.as_bytes());
+
+        file.write_all(&local_header)?;
+        file.write_all(&data)?;
+        offset += local_header.len() + data.len();
+    }
+
+    let mut end = Vec::new();
+    push_u32(&mut end, 0x0605_4B50);
+    push_u16(&mut end, 0);
+    push_u16(&mut end, 0);
+    push_u16(&mut end, entries);
+    push_u16(&mut end, entries);
+    push_u32(&mut end, zip_u32(central_directory.len(), "central directory size")?);
+    push_u32(&mut end, zip_u32(offset, "central directory offset")?);
+    push_u16(&mut end, 0);
+
+    file.write_all(&central_directory)?;
+    file.write_all(&end)?;
+    file.flush()
+}
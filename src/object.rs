@@ -1,4 +1,5 @@
 use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
 use field::Field;
 
@@ -119,11 +120,25 @@ impl Move for Player {
     }
 }
 
+#[derive(Clone, Debug)]
+pub enum EnemyKind {
+    //毎フレームプレイヤーの方へ向き直る
+    Chaser,
+    //出現時の速度のまま進み、壁で跳ね返る
+    Straight,
+    //経由点を順にたどり、最後まで行ったら最初に戻る
+    Path(Vec<Position>),
+}
+
 #[derive(Clone)]
 pub struct Enemy {
     pub pos: Position,
     pub vector: Vector,
     pub state: State,
+    pub kind: EnemyKind,
+    pub speed: f32,
+    //Pathで次に向かう経由点
+    pub waypoint: usize,
 }
 
 impl Hash for Enemy {
@@ -234,3 +249,15 @@ impl Command {
         COMMANDS.into_iter()
     }
 }
+
+//Debug表記 ("Move(Left)" など) から読み戻す
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Command, String> {
+        Command::iterator()
+            .find(|cmd| format!("{:?}", cmd) == s.trim())
+            .cloned()
+            .ok_or_else(|| format!("unknown command: {}", s))
+    }
+}
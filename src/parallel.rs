@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use field::Field;
+use file_reader::Pattern;
+use interrupt;
+use object::Command;
+use q_learning::{self, Learner, QTable, State};
+use rng::XorShift;
+use training_log::{EpisodeRecord, TrainingLog};
+
+const SHARDS_PER_THREAD: usize = 16;
+
+//状態ごとにロックを分けたQテーブル
+//更新は1つの状態の行しか触らないので、その状態のシャードだけロックすればよい
+pub struct ShardedQTable {
+    shards: Vec<Mutex<QTable>>,
+}
+
+impl ShardedQTable {
+    pub fn new(q_table: QTable, shard_count: usize) -> ShardedQTable {
+        let mut shards: Vec<QTable> = (0..shard_count).map(|_| HashMap::new()).collect();
+        for (state, row) in q_table {
+            shards[(state % shard_count as u64) as usize].insert(state, row);
+        }
+        ShardedQTable { shards: shards.into_iter().map(Mutex::new).collect() }
+    }
+
+    fn shard(&self, state: State) -> &Mutex<QTable> {
+        &self.shards[(state % self.shards.len() as u64) as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+
+    pub fn into_q_table(self) -> QTable {
+        let mut q_table = HashMap::new();
+        for shard in self.shards {
+            q_table.extend(shard.into_inner().unwrap());
+        }
+        q_table
+    }
+}
+
+impl<'a> Learner for &'a ShardedQTable {
+    fn select(&mut self, state: State, epsilon: f64, rng: &mut XorShift) -> Command {
+        self.shard(state).lock().unwrap().select(state, epsilon, rng)
+    }
+
+    fn learn(&mut self, state: State, command: Command, reward: f64) {
+        self.shard(state).lock().unwrap().learn(state, command, reward);
+    }
+}
+
+//各エピソードの環境のシードはエピソード番号だけから決まり、スレッド数によらず同じになる
+//Qテーブルの更新順はスレッドの進み方で変わるので、学習結果そのものは再現しない
+fn episode_seed(base_seed: u64, episode: usize) -> u64 {
+    XorShift::new(base_seed.wrapping_add((episode as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)))
+        .next_u64()
+}
+
+//threads本のスレッドがそれぞれ自分のFieldでエピソードを回し、共有のQテーブルを更新する
+//ログは呼び出し元のスレッドでまとめて書き出す
+pub fn train(
+    q_table: QTable,
+    threads: usize,
+    episodes: usize,
+    epsilon: f64,
+    base_seed: u64,
+    (width, height): (u32, u32),
+    pattern: &Pattern,
+    log: &mut TrainingLog,
+) -> io::Result<QTable> {
+    let (sender, receiver) = mpsc::channel::<EpisodeRecord>();
+    let table = Arc::new(ShardedQTable::new(q_table, threads * SHARDS_PER_THREAD));
+    let next_episode = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..threads)
+        .map(|_| {
+            let table = table.clone();
+            let next_episode = next_episode.clone();
+            let sender = sender.clone();
+            let pattern = pattern.clone();
+            thread::spawn(move || {
+                let mut field = Field::new(width, height, &pattern);
+                loop {
+                    let episode = next_episode.fetch_add(1, Ordering::SeqCst);
+                    if episode >= episodes || interrupt::requested() {
+                        break;
+                    }
+                    let seed = episode_seed(base_seed, episode);
+                    let mut rng = XorShift::new(seed);
+                    let total_reward =
+                        q_learning::train_episode(&mut field, &mut &*table, epsilon, &mut rng);
+                    let record = EpisodeRecord::from_field(
+                        episode,
+                        seed,
+                        &field,
+                        total_reward,
+                        epsilon,
+                        table.len(),
+                    );
+                    if sender.send(record).is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+    for record in receiver {
+        log.write(&record)?;
+    }
+    for worker in workers {
+        worker.join().unwrap();
+    }
+    match Arc::try_unwrap(table) {
+        Ok(table) => Ok(table.into_q_table()),
+        Err(_) => unreachable!("all workers have been joined"),
+    }
+}
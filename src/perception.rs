@@ -0,0 +1,95 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::fmt;
+use std::str::FromStr;
+use field::Field;
+use q_learning::State;
+
+//Fieldを状態番号にする方法
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum StateEncoder {
+    //知覚ビットだけ (Field::get_hash)
+    Perception,
+    //知覚ビットと残弾数
+    PerceptionBullets,
+}
+
+impl StateEncoder {
+    pub fn encode(&self, field: &Field) -> State {
+        match *self {
+            StateEncoder::Perception => field.get_hash(),
+            StateEncoder::PerceptionBullets => {
+                let mut hasher = DefaultHasher::new();
+                field.perception().hash(&mut hasher);
+                field.player.remain_bullet.hash(&mut hasher);
+                hasher.finish()
+            }
+        }
+    }
+}
+
+impl FromStr for StateEncoder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StateEncoder, String> {
+        match s {
+            "perception" => Ok(StateEncoder::Perception),
+            "perception-bullets" => Ok(StateEncoder::PerceptionBullets),
+            _ => Err(format!("unknown state encoder: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for StateEncoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateEncoder::Perception => write!(f, "perception"),
+            StateEncoder::PerceptionBullets => write!(f, "perception-bullets"),
+        }
+    }
+}
+
+//Field::perception のビットの意味 (下位ビットから)
+pub const BIT_NAMES: [&str; 8] = [
+    "upper-left box",
+    "upper-right box",
+    "lower-left box",
+    "lower-right box",
+    "left ray",
+    "right ray",
+    "down ray",
+    "up ray",
+];
+
+pub fn seen(bits: u8, bit: usize) -> bool {
+    bits & (1 << bit) != 0
+}
+
+//知覚ビットを自機(P)中心の5x5の図にする
+//箱は敵がいれば#、いなければo。直線は敵がいれば*、いなければ-か|
+pub fn diagram(bits: u8) -> String {
+    let cell = |bit: usize, on: char, off: char| if seen(bits, bit) { on } else { off };
+    let rows = [
+        [cell(0, '#', 'o'), ' ', cell(7, '*', '|'), ' ', cell(1, '#', 'o')],
+        [' ', ' ', cell(7, '*', '|'), ' ', ' '],
+        [cell(4, '*', '-'), cell(4, '*', '-'), 'P', cell(5, '*', '-'), cell(5, '*', '-')],
+        [' ', ' ', cell(6, '*', '|'), ' ', ' '],
+        [cell(2, '#', 'o'), ' ', cell(6, '*', '|'), ' ', cell(3, '#', 'o')],
+    ];
+    rows.iter()
+        .map(|row| row.iter().collect::<String>().trim_end().to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+//知覚ビットから状態番号を作る。Field::get_hash と同じ値になる
+pub fn encode(bits: u8) -> State {
+    let mut hasher = DefaultHasher::new();
+    bits.hash(&mut hasher);
+    hasher.finish()
+}
+
+//状態番号から知覚ビットを戻す。知覚から作られた状態でなければNone
+pub fn decode(state: State) -> Option<u8> {
+    (0..256u32).map(|bits| bits as u8).find(|&bits| encode(bits) == state)
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::str::FromStr;
+use std::io::{self, Seek, SeekFrom};
+use bincode;
+use checkpoint;
+use object::Command;
+use field::{Field, EPISODE_TICK_LIMIT};
+use perception::StateEncoder;
+use rng::XorShift;
+
+pub static ALPHA: f64 = 0.1;
+pub static DISCOUNT_RATE: f64 = 0.92;
+
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Hyperparameters {
+    pub alpha: f64,
+    pub discount_rate: f64,
+}
+
+impl Default for Hyperparameters {
+    fn default() -> Hyperparameters {
+        Hyperparameters {
+            alpha: ALPHA,
+            discount_rate: DISCOUNT_RATE,
+        }
+    }
+}
+
+//エピソード番号ごとのε
+#[derive(Copy, Clone, Debug)]
+pub enum EpsilonSchedule {
+    Constant(f64),
+    //episodesエピソードかけてstartからendまで線形に下げる
+    Linear { start: f64, end: f64, episodes: usize },
+    //毎エピソードdecay倍して、endより下げない
+    Exponential { start: f64, end: f64, decay: f64 },
+}
+
+impl EpsilonSchedule {
+    pub fn at(&self, episode: usize) -> f64 {
+        match *self {
+            EpsilonSchedule::Constant(epsilon) => epsilon,
+            EpsilonSchedule::Linear { start, end, episodes } => {
+                let t = (episode as f64 / episodes.max(1) as f64).min(1.0);
+                start + (end - start) * t
+            }
+            EpsilonSchedule::Exponential { start, end, decay } => {
+                (start * decay.powi(episode as i32)).max(end)
+            }
+        }
+    }
+}
+
+//const:0.3 / linear:1.0:0.05:500 / exp:1.0:0.05:0.99
+impl FromStr for EpsilonSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EpsilonSchedule, String> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let number = |i: usize| -> Result<f64, String> {
+            parts
+                .get(i)
+                .ok_or(format!("missing value in epsilon schedule: {}", s))?
+                .parse()
+                .map_err(|_| format!("invalid epsilon schedule: {}", s))
+        };
+        match parts[0] {
+            "const" if parts.len() == 2 => Ok(EpsilonSchedule::Constant(number(1)?)),
+            "linear" if parts.len() == 4 => {
+                Ok(EpsilonSchedule::Linear {
+                    start: number(1)?,
+                    end: number(2)?,
+                    episodes: number(3)? as usize,
+                })
+            }
+            "exp" if parts.len() == 4 => {
+                Ok(EpsilonSchedule::Exponential {
+                    start: number(1)?,
+                    end: number(2)?,
+                    decay: number(3)?,
+                })
+            }
+            _ => Err(format!("invalid epsilon schedule: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for EpsilonSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EpsilonSchedule::Constant(epsilon) => write!(f, "const:{}", epsilon),
+            EpsilonSchedule::Linear { start, end, episodes } => {
+                write!(f, "linear:{}:{}:{}", start, end, episodes)
+            }
+            EpsilonSchedule::Exponential { start, end, decay } => {
+                write!(f, "exp:{}:{}:{}", start, end, decay)
+            }
+        }
+    }
+}
+
+//q_table.binの先頭に置く印。これが無ければ訪問回数を持たない古い形式として読む
+const Q_TABLE_MAGIC: u64 = 0x5154_4142_4C45_0002;
+
+pub type State = u64;
+pub type QTable = HashMap<State, HashMap<Command, QValue>>;
+//訪問回数を持たない古い形式
+pub type LegacyQTable = HashMap<State, HashMap<Command, f64>>;
+
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct QValue {
+    pub value: f64,
+    pub visits: u64,
+}
+
+pub fn load(path: &str) -> bincode::Result<QTable> {
+    let mut file = File::open(path)?;
+    let magic: u64 = bincode::deserialize_from(&mut file).unwrap_or(0);
+    if magic == Q_TABLE_MAGIC {
+        return bincode::deserialize_from(file);
+    }
+    file.seek(SeekFrom::Start(0))?;
+    let legacy: LegacyQTable = bincode::deserialize_from(file)?;
+    Ok(from_legacy(legacy))
+}
+
+//ファイルがなければ空の表から始める。読めないファイルは空扱いにせずエラーにする
+pub fn load_or_empty(path: &str) -> bincode::Result<QTable> {
+    match File::open(path) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        _ => load(path),
+    }
+}
+
+//古い形式の表は訪問回数0として読む
+pub fn from_legacy(legacy: LegacyQTable) -> QTable {
+    legacy
+        .into_iter()
+        .map(|(state, row)| {
+            let row = row.into_iter()
+                .map(|(command, value)| {
+                    (
+                        command,
+                        QValue {
+                            value: value,
+                            visits: 0,
+                        },
+                    )
+                })
+                .collect();
+            (state, row)
+        })
+        .collect()
+}
+
+pub fn save(path: &str, q_table: &QTable) -> bincode::Result<()> {
+    checkpoint::write_atomic(path, &(Q_TABLE_MAGIC, q_table))
+}
+
+//Q値が最大のコマンド
+pub fn greedy(row: &HashMap<Command, QValue>) -> Option<Command> {
+    let mut sorted_command_qval: Vec<(&Command, &QValue)> = row.iter().collect();
+    sorted_command_qval.sort_by(|a, b| b.1.value.partial_cmp(&a.1.value).unwrap());
+    sorted_command_qval.first().map(|&(&cmd, _)| cmd)
+}
+
+pub fn command_select(
+    q_table: &QTable,
+    state: State,
+    epsilon: f64,
+    rng: &mut XorShift,
+) -> Command {
+    let random_num: usize = rng.gen_range(0, 100);
+    if random_num < (epsilon * 100.0) as usize || !q_table.contains_key(&state) ||
+        q_table.get(&state).unwrap().len() == 0
+    {
+        let random_command_num = rng.gen_range(0, 13);
+        *Command::iterator().nth(random_command_num).unwrap()
+    } else {
+        greedy(q_table.get(&state).unwrap()).unwrap_or(Command::Stay)
+    }
+}
+
+pub fn update(q_table: &mut QTable, state: State, command: Command, reward: f64) {
+    update_with(q_table, state, command, reward, &Hyperparameters::default());
+}
+
+pub fn update_with(
+    q_table: &mut QTable,
+    state: State,
+    command: Command,
+    reward: f64,
+    hyper: &Hyperparameters,
+) {
+    let row = q_table.entry(state).or_insert(HashMap::new());
+    let current = row.get(&command).cloned().unwrap_or_default();
+    let q_value = current.value +
+        hyper.alpha *
+            (reward +
+                 hyper.discount_rate *
+                     Command::iterator()
+                         .map(|command| row.get(&command).map(|q| q.value))
+                         .map(|value| value.unwrap_or(0.0))
+                         .fold(0.0 / 0.0, |m, v| v.max(m)) -
+                 current.value);
+    row.insert(
+        command,
+        QValue {
+            value: q_value,
+            visits: current.visits + 1,
+        },
+    );
+}
+
+//行動の選択と価値の更新ができるもの
+pub trait Learner {
+    fn select(&mut self, state: State, epsilon: f64, rng: &mut XorShift) -> Command;
+    fn learn(&mut self, state: State, command: Command, reward: f64);
+}
+
+impl Learner for QTable {
+    fn select(&mut self, state: State, epsilon: f64, rng: &mut XorShift) -> Command {
+        self.entry(state).or_insert(HashMap::new());
+        command_select(self, state, epsilon, rng)
+    }
+
+    fn learn(&mut self, state: State, command: Command, reward: f64) {
+        update(self, state, command, reward);
+    }
+}
+
+//既定値以外のハイパーパラメータで学習するQテーブル
+pub struct Tuned<'a> {
+    pub q_table: &'a mut QTable,
+    pub hyper: Hyperparameters,
+}
+
+impl<'a> Learner for Tuned<'a> {
+    fn select(&mut self, state: State, epsilon: f64, rng: &mut XorShift) -> Command {
+        self.q_table.select(state, epsilon, rng)
+    }
+
+    fn learn(&mut self, state: State, command: Command, reward: f64) {
+        update_with(self.q_table, state, command, reward, &self.hyper);
+    }
+}
+
+//1エピソード分学習して、得た報酬の合計を返す
+pub fn train_episode<L: Learner>(
+    field: &mut Field,
+    learner: &mut L,
+    epsilon: f64,
+    rng: &mut XorShift,
+) -> f64 {
+    train_episode_with(field, learner, StateEncoder::Perception, epsilon, rng)
+}
+
+pub fn train_episode_with<L: Learner>(
+    field: &mut Field,
+    learner: &mut L,
+    encoder: StateEncoder,
+    epsilon: f64,
+    rng: &mut XorShift,
+) -> f64 {
+    field.reset_with(rng);
+    let mut total_reward = 0.0;
+    let mut state: State = encoder.encode(field);
+    while !field.game_end && field.tick < EPISODE_TICK_LIMIT {
+        let command = learner.select(state, epsilon, rng);
+        let reward = field.step(command);
+        learner.learn(state, command, reward);
+        total_reward += reward;
+        state = encoder.encode(field);
+    }
+    total_reward
+}
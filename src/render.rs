@@ -1,5 +1,6 @@
 use glium;
 use glium::Surface;
+use glium::glutin::{EventsLoop, WindowBuilder, ContextBuilder};
 use object::Position;
 
 const PI: f32 = 3.1415;
@@ -16,6 +17,12 @@ pub struct Render<'a> {
     target: Option<glium::Frame>,
 }
 
+pub fn create_display(width: u32, height: u32, events_loop: &EventsLoop) -> glium::Display {
+    let window = WindowBuilder::new().with_dimensions(width, height);
+    let context = ContextBuilder::new();
+    glium::Display::new(window, context, events_loop).unwrap()
+}
+
 #[derive(Copy, Clone)]
 struct Vertex {
     position: [f32; 2],
@@ -0,0 +1,79 @@
+use std::fmt::Write;
+use object::Command;
+use perception;
+use q_learning::{self, QTable, State};
+
+//各状態の知覚の図、貪欲なコマンド、Q値の幅をMarkdownにまとめる
+pub fn markdown(q_table: &QTable) -> String {
+    let mut states: Vec<(State, u64)> = q_table
+        .iter()
+        .map(|(&state, row)| (state, row.values().map(|q| q.visits).sum()))
+        .collect();
+    //よく訪れた状態から並べる
+    states.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut out = String::new();
+    writeln!(out, "# Policy report").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "{} states.", states.len()).unwrap();
+    writeln!(out).unwrap();
+    writeln!(
+        out,
+        "`#`/`o`: zone box with/without an enemy, `*`: ray that sees an enemy, `P`: player."
+    ).unwrap();
+
+    for &(state, visits) in states.iter() {
+        let row = &q_table[&state];
+        writeln!(out).unwrap();
+        match perception::decode(state) {
+            Some(bits) => {
+                writeln!(out, "## State {:08b} ({:016x})", bits, state).unwrap();
+                writeln!(out).unwrap();
+                writeln!(out, "```").unwrap();
+                writeln!(out, "{}", perception::diagram(bits)).unwrap();
+                writeln!(out, "```").unwrap();
+                writeln!(out).unwrap();
+                let seen: Vec<&str> = (0..8)
+                    .filter(|&bit| perception::seen(bits, bit))
+                    .map(|bit| perception::BIT_NAMES[bit])
+                    .collect();
+                if seen.is_empty() {
+                    writeln!(out, "Sees no enemy.").unwrap();
+                } else {
+                    writeln!(out, "Sees an enemy in: {}.", seen.join(", ")).unwrap();
+                }
+            }
+            None => {
+                writeln!(out, "## State {:016x} (not a perception state)", state).unwrap();
+            }
+        }
+        writeln!(out).unwrap();
+
+        let values: Vec<f64> = row.values().map(|q| q.value).collect();
+        let max = values.iter().cloned().fold(0.0 / 0.0, f64::max);
+        let min = values.iter().cloned().fold(0.0 / 0.0, f64::min);
+        match q_learning::greedy(row) {
+            Some(command) => {
+                writeln!(
+                    out,
+                    "Greedy: `{:?}`, Q-value spread {:.3} ({:.3} .. {:.3}), {} visits.",
+                    command,
+                    max - min,
+                    min,
+                    max,
+                    visits
+                ).unwrap()
+            }
+            None => writeln!(out, "No command has been tried yet.").unwrap(),
+        }
+        writeln!(out).unwrap();
+        writeln!(out, "| command | value | visits |").unwrap();
+        writeln!(out, "|---|---:|---:|").unwrap();
+        for command in Command::iterator() {
+            if let Some(q) = row.get(command) {
+                writeln!(out, "| {:?} | {:.3} | {} |", command, q.value, q.visits).unwrap();
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,35 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//シードと状態を保存して再現できる乱数生成器 (xorshift64*)
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> XorShift {
+        //状態が0だと0しか出なくなる
+        let state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+        XorShift { state: state }
+    }
+
+    pub fn from_time() -> XorShift {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        XorShift::new(now.as_secs() ^ ((now.subsec_nanos() as u64) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn gen_range(&mut self, low: usize, high: usize) -> usize {
+        low + (self.next_u64() % (high - low) as u64) as usize
+    }
+}
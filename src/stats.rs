@@ -0,0 +1,65 @@
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Summary {
+    pub mean: f64,
+    pub median: f64,
+    pub std: f64,
+}
+
+impl Summary {
+    pub fn from_samples(samples: &[f64]) -> Summary {
+        if samples.is_empty() {
+            return Summary {
+                mean: 0.0,
+                median: 0.0,
+                std: 0.0,
+            };
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|v| (v - mean).powf(2.0)).sum::<f64>() / n;
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mid = sorted.len() / 2;
+        let median = if sorted.len() % 2 == 0 {
+            (sorted[mid - 1] + sorted[mid]) / 2.0
+        } else {
+            sorted[mid]
+        };
+
+        Summary {
+            mean: mean,
+            median: median,
+            std: variance.sqrt(),
+        }
+    }
+}
+
+//値を溜めずに平均と分散を求める (Welford法)
+#[derive(Copy, Clone, Debug, Default, Serialize)]
+pub struct RunningStat {
+    pub count: u64,
+    pub mean: f64,
+    m2: f64,
+}
+
+impl RunningStat {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn variance(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.m2 / self.count as f64
+        }
+    }
+
+    pub fn std(&self) -> f64 {
+        self.variance().sqrt()
+    }
+}
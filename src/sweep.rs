@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use evaluation::{self, EpisodeResult};
+use field::Field;
+use file_reader::Pattern;
+use interrupt;
+use perception::StateEncoder;
+use q_learning::{self, EpsilonSchedule, Hyperparameters, QTable, Tuned};
+use rng::XorShift;
+use stats::Summary;
+
+//値の候補を並べたものか、ランダムサーチ用の範囲
+#[derive(Clone, Debug)]
+pub enum Param {
+    Values(Vec<f64>),
+    Range(f64, f64),
+}
+
+//0.05,0.1,0.2 または 0.01..0.5
+impl FromStr for Param {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Param, String> {
+        let number = |v: &str| v.trim().parse::<f64>().map_err(|_| format!("invalid value: {}", s));
+        if let Some(i) = s.find("..") {
+            return Ok(Param::Range(number(&s[..i])?, number(&s[i + 2..])?));
+        }
+        let values: Result<Vec<f64>, String> = s.split(',').map(|v| number(v)).collect();
+        Ok(Param::Values(values?))
+    }
+}
+
+impl Param {
+    fn sample(&self, rng: &mut XorShift) -> f64 {
+        match *self {
+            Param::Values(ref values) => values[rng.gen_range(0, values.len())],
+            Param::Range(low, high) => low + (high - low) * rng.next_f64(),
+        }
+    }
+}
+
+pub struct Space {
+    pub alpha: Param,
+    pub discount_rate: Param,
+    pub epsilon: Vec<EpsilonSchedule>,
+    pub encoder: Vec<StateEncoder>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub hyper: Hyperparameters,
+    pub epsilon: EpsilonSchedule,
+    pub encoder: StateEncoder,
+}
+
+impl Space {
+    //全組み合わせ。範囲が含まれていればエラー
+    pub fn grid(&self) -> Result<Vec<Config>, String> {
+        let values = |param: &Param| match *param {
+            Param::Values(ref values) => Ok(values.clone()),
+            Param::Range(..) => Err("ranges can only be used with --random".to_string()),
+        };
+        let mut configs = Vec::new();
+        for &alpha in values(&self.alpha)?.iter() {
+            for &discount_rate in values(&self.discount_rate)?.iter() {
+                for &epsilon in self.epsilon.iter() {
+                    for &encoder in self.encoder.iter() {
+                        configs.push(Config {
+                            hyper: Hyperparameters {
+                                alpha: alpha,
+                                discount_rate: discount_rate,
+                            },
+                            epsilon: epsilon,
+                            encoder: encoder,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(configs)
+    }
+
+    pub fn random(&self, count: usize, rng: &mut XorShift) -> Vec<Config> {
+        (0..count)
+            .map(|_| {
+                Config {
+                    hyper: Hyperparameters {
+                        alpha: self.alpha.sample(rng),
+                        discount_rate: self.discount_rate.sample(rng),
+                    },
+                    epsilon: self.epsilon[rng.gen_range(0, self.epsilon.len())],
+                    encoder: self.encoder[rng.gen_range(0, self.encoder.len())],
+                }
+            })
+            .collect()
+    }
+}
+
+pub struct Settings {
+    pub seeds: usize,
+    pub episodes: usize,
+    pub eval_episodes: usize,
+    pub threads: usize,
+    pub size: (u32, u32),
+    pub pattern: Pattern,
+}
+
+//1つの設定・1つのシードで学習し、貪欲方策の平均スコアを返す
+fn run_one(config: &Config, seed: u64, settings: &Settings) -> f64 {
+    let (width, height) = settings.size;
+    let mut field = Field::new(width, height, &settings.pattern);
+    let mut q_table: QTable = HashMap::new();
+    let mut rng = XorShift::new(seed);
+    for episode in 0..settings.episodes {
+        if interrupt::requested() {
+            break;
+        }
+        let mut learner = Tuned {
+            q_table: &mut q_table,
+            hyper: config.hyper,
+        };
+        q_learning::train_episode_with(
+            &mut field,
+            &mut learner,
+            config.encoder,
+            config.epsilon.at(episode),
+            &mut rng,
+        );
+    }
+    let mut eval_rng = XorShift::new(seed);
+    let results: Vec<EpisodeResult> = (0..settings.eval_episodes)
+        .map(|_| {
+            evaluation::run_greedy_episode_with(&mut field, &q_table, config.encoder, &mut eval_rng)
+        })
+        .collect();
+    let scores: Vec<f64> = results.iter().map(|r| r.score as f64).collect();
+    Summary::from_samples(&scores).mean
+}
+
+pub struct Ranked {
+    pub config: Config,
+    pub scores: Vec<f64>,
+    pub mean: f64,
+    //平均の95%信頼区間の半幅。シードが1つだけなら求められないのでNone
+    pub ci95: Option<f64>,
+}
+
+//自由度dfのt分布の両側95%点。表に無い自由度は小さい側(広い区間)の値を使う
+fn t_critical_95(df: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+        2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+        2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+    ];
+    match df {
+        0 => f64::NAN,
+        1..=30 => TABLE[df - 1],
+        31..=40 => 2.042,
+        41..=60 => 2.021,
+        61..=120 => 2.000,
+        121..=1000 => 1.980,
+        _ => 1.960,
+    }
+}
+
+//設定×シードの組をスレッドで分けて回し、平均スコアの高い順に並べる
+pub fn run(configs: Vec<Config>, settings: Settings) -> Vec<Ranked> {
+    let jobs: Vec<(usize, u64)> = (0..configs.len())
+        .flat_map(|config| (0..settings.seeds).map(move |seed| (config, seed as u64)))
+        .collect();
+    let configs = Arc::new(configs);
+    let settings = Arc::new(settings);
+    let jobs = Arc::new(jobs);
+    let next_job = Arc::new(AtomicUsize::new(0));
+    let scores = Arc::new(Mutex::new(vec![Vec::new(); configs.len()]));
+
+    let workers: Vec<_> = (0..settings.threads.max(1))
+        .map(|_| {
+            let (configs, settings, jobs, next_job, scores) = (
+                configs.clone(),
+                settings.clone(),
+                jobs.clone(),
+                next_job.clone(),
+                scores.clone(),
+            );
+            thread::spawn(move || loop {
+                let job = next_job.fetch_add(1, Ordering::SeqCst);
+                if job >= jobs.len() || interrupt::requested() {
+                    break;
+                }
+                let (config, seed) = jobs[job];
+                let score = run_one(&configs[config], seed, &settings);
+                println!(
+                    "[{}/{}] alpha={:.4} discount={:.4} epsilon={} encoder={} seed={}: {:.2}",
+                    job + 1,
+                    jobs.len(),
+                    configs[config].hyper.alpha,
+                    configs[config].hyper.discount_rate,
+                    configs[config].epsilon,
+                    configs[config].encoder,
+                    seed,
+                    score
+                );
+                scores.lock().unwrap()[config].push(score);
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let scores = scores.lock().unwrap();
+    let mut ranked: Vec<Ranked> = configs
+        .iter()
+        .zip(scores.iter())
+        .filter(|&(_, scores)| !scores.is_empty())
+        .map(|(config, scores)| {
+            let n = scores.len() as f64;
+            let mean = scores.iter().sum::<f64>() / n;
+            let ci95 = if scores.len() > 1 {
+                let sample_std = (scores.iter().map(|s| (s - mean).powf(2.0)).sum::<f64>() / (n - 1.0)).sqrt();
+                Some(t_critical_95(scores.len() - 1) * sample_std / n.sqrt())
+            } else {
+                None
+            };
+            Ranked {
+                config: config.clone(),
+                scores: scores.clone(),
+                mean: mean,
+                ci95: ci95,
+            }
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.mean.partial_cmp(&a.mean).unwrap());
+    ranked
+}
+
+pub const CSV_HEADER: &str = "rank,alpha,discount_rate,epsilon,encoder,seeds,mean,ci95_low,ci95_high";
+
+//区間が求められないときは ci95_low, ci95_high を空にする
+pub fn csv_row(rank: usize, ranked: &Ranked) -> String {
+    let (low, high) = match ranked.ci95 {
+        Some(ci95) => ((ranked.mean - ci95).to_string(), (ranked.mean + ci95).to_string()),
+        None => (String::new(), String::new()),
+    };
+    format!(
+        "{},{},{},{},{},{},{},{},{}",
+        rank,
+        ranked.config.hyper.alpha,
+        ranked.config.hyper.discount_rate,
+        ranked.config.epsilon,
+        ranked.config.encoder,
+        ranked.scores.len(),
+        ranked.mean,
+        low,
+        high
+    )
+}
+
+pub fn print_table(ranked: &[Ranked]) {
+    println!(
+        "{:>4} {:>8} {:>8} {:<24} {:<20} {:>5} {:>12} {:>10}",
+        "rank",
+        "alpha",
+        "discount",
+        "epsilon",
+        "encoder",
+        "seeds",
+        "mean",
+        "95% CI"
+    );
+    for (i, r) in ranked.iter().enumerate() {
+        println!(
+            "{:>4} {:>8.4} {:>8.4} {:<24} {:<20} {:>5} {:>12.2} {:>10}",
+            i + 1,
+            r.config.hyper.alpha,
+            r.config.hyper.discount_rate,
+            r.config.epsilon.to_string(),
+            r.config.encoder.to_string(),
+            r.scores.len(),
+            r.mean,
+            match r.ci95 {
+                Some(ci95) => format!("±{:.2}", ci95),
+                None => "n<2".to_string(),
+            }
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use serde_json;
+use object::Command;
+use perception;
+use q_learning::{QTable, QValue, State};
+
+//Qテーブルの1マス分。JSONでもCSVでも同じ形で並べる
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub state: State,
+    //知覚ビットを上位ビットから書いたもの。知覚から作られた状態でなければ空
+    #[serde(default)]
+    pub perception: String,
+    pub command: String,
+    pub value: f64,
+    pub visits: u64,
+}
+
+const CSV_HEADER: &str = "state,perception,command,value,visits";
+
+fn is_json(path: &str) -> bool {
+    Path::new(path).extension().and_then(|ext| ext.to_str()) == Some("json")
+}
+
+pub fn perception_column(state: State) -> String {
+    perception::decode(state)
+        .map(|bits| format!("{:08b}", bits))
+        .unwrap_or(String::new())
+}
+
+//状態番号順、状態内はCommand::iteratorの順に並べる
+pub fn entries(q_table: &QTable) -> Vec<Entry> {
+    let mut states: Vec<&State> = q_table.keys().collect();
+    states.sort();
+    let mut entries = Vec::new();
+    for &state in states {
+        let row = &q_table[&state];
+        let perception = perception_column(state);
+        for command in Command::iterator() {
+            if let Some(q) = row.get(command) {
+                entries.push(Entry {
+                    state: state,
+                    perception: perception.clone(),
+                    command: format!("{:?}", command),
+                    value: q.value,
+                    visits: q.visits,
+                });
+            }
+        }
+    }
+    entries
+}
+
+//NaNや無限大はJSONではnullになって読み戻せないので、どちらの形式でも書き出さない
+pub fn export(q_table: &QTable, path: &str) -> Result<(), Box<Error>> {
+    let entries = entries(q_table);
+    if let Some(entry) = entries.iter().find(|entry| !entry.value.is_finite()) {
+        return Err(format!(
+            "state {} command {} has a non-finite value ({})",
+            entry.state,
+            entry.command,
+            entry.value
+        ).into());
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    if is_json(path) {
+        serde_json::to_writer_pretty(&mut file, &entries)?;
+        writeln!(file)?;
+    } else {
+        writeln!(file, "{}", CSV_HEADER)?;
+        for entry in entries {
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                entry.state,
+                entry.perception,
+                entry.command,
+                entry.value,
+                entry.visits
+            )?;
+        }
+    }
+    file.flush()?;
+    Ok(())
+}
+
+pub fn import(path: &str) -> Result<QTable, Box<Error>> {
+    let entries: Vec<Entry> = if is_json(path) {
+        serde_json::from_reader(BufReader::new(File::open(path)?))?
+    } else {
+        let mut entries = Vec::new();
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if i == 0 || line.trim().is_empty() {
+                continue;
+            }
+            let columns: Vec<&str> = line.trim().split(',').collect();
+            if columns.len() != 5 {
+                return Err(format!("{}:{}: expected 5 columns", path, i + 1).into());
+            }
+            //NaNやinfはparseを通るので、exportと同じく弾く
+            let value = match columns[3].parse::<f64>() {
+                Ok(value) if value.is_finite() => value,
+                _ => {
+                    return Err(format!("{}:{}: invalid value `{}`", path, i + 1, columns[3]).into())
+                }
+            };
+            entries.push(Entry {
+                state: columns[0].parse()?,
+                perception: columns[1].to_string(),
+                command: columns[2].to_string(),
+                value: value,
+                visits: columns[4].parse()?,
+            });
+        }
+        entries
+    };
+
+    let mut q_table: QTable = HashMap::new();
+    for entry in entries {
+        let command: Command = entry.command.parse()?;
+        q_table.entry(entry.state).or_insert(HashMap::new()).insert(
+            command,
+            QValue {
+                value: entry.value,
+                visits: entry.visits,
+            },
+        );
+    }
+    Ok(q_table)
+}
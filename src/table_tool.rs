@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use object::Command;
+use q_learning::{self, QTable, QValue, State};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MergeStrategy {
+    //訪問回数で重み付けした平均
+    VisitWeighted,
+    Max,
+}
+
+pub fn merge(tables: &[QTable], strategy: MergeStrategy) -> QTable {
+    let mut collected: HashMap<State, HashMap<Command, Vec<QValue>>> = HashMap::new();
+    for table in tables {
+        for (&state, row) in table {
+            let merged_row = collected.entry(state).or_insert(HashMap::new());
+            for (&command, &q) in row {
+                merged_row.entry(command).or_insert(Vec::new()).push(q);
+            }
+        }
+    }
+    collected
+        .into_iter()
+        .map(|(state, row)| {
+            let row = row.into_iter()
+                .map(|(command, values)| (command, merge_values(&values, strategy)))
+                .collect();
+            (state, row)
+        })
+        .collect()
+}
+
+fn merge_values(values: &[QValue], strategy: MergeStrategy) -> QValue {
+    let visits: u64 = values.iter().map(|q| q.visits).sum();
+    let value = match strategy {
+        MergeStrategy::Max => values.iter().map(|q| q.value).fold(0.0 / 0.0, f64::max),
+        //訪問回数が分からない古い表が混ざっていれば、その重みが0にならないよう単純平均にする
+        MergeStrategy::VisitWeighted if values.iter().any(|q| q.visits == 0) => {
+            values.iter().map(|q| q.value).sum::<f64>() / values.len() as f64
+        }
+        MergeStrategy::VisitWeighted => {
+            values.iter().map(|q| q.value * q.visits as f64).sum::<f64>() / visits as f64
+        }
+    };
+    QValue {
+        value: value,
+        visits: visits,
+    }
+}
+
+pub struct ValueChange {
+    pub state: State,
+    pub command: Command,
+    pub before: f64,
+    pub after: f64,
+}
+
+pub struct GreedyChange {
+    pub state: State,
+    pub before: Command,
+    pub after: Command,
+}
+
+pub struct Diff {
+    pub only_in_a: Vec<State>,
+    pub only_in_b: Vec<State>,
+    //変化の大きい順
+    pub value_changes: Vec<ValueChange>,
+    pub greedy_changes: Vec<GreedyChange>,
+}
+
+pub fn diff(a: &QTable, b: &QTable) -> Diff {
+    let mut only_in_a: Vec<State> = a.keys().filter(|state| !b.contains_key(state)).cloned().collect();
+    let mut only_in_b: Vec<State> = b.keys().filter(|state| !a.contains_key(state)).cloned().collect();
+    only_in_a.sort();
+    only_in_b.sort();
+
+    let mut value_changes = Vec::new();
+    let mut greedy_changes = Vec::new();
+    for (&state, row_a) in a {
+        let row_b = match b.get(&state) {
+            Some(row_b) => row_b,
+            None => continue,
+        };
+        for &command in Command::iterator() {
+            let before = row_a.get(&command).map(|q| q.value).unwrap_or(0.0);
+            let after = row_b.get(&command).map(|q| q.value).unwrap_or(0.0);
+            if before != after {
+                value_changes.push(ValueChange {
+                    state: state,
+                    command: command,
+                    before: before,
+                    after: after,
+                });
+            }
+        }
+        match (q_learning::greedy(row_a), q_learning::greedy(row_b)) {
+            (Some(before), Some(after)) if before != after => {
+                greedy_changes.push(GreedyChange {
+                    state: state,
+                    before: before,
+                    after: after,
+                })
+            }
+            _ => (),
+        }
+    }
+    value_changes.sort_by(|x, y| {
+        (y.after - y.before).abs().partial_cmp(&(x.after - x.before).abs()).unwrap()
+    });
+    greedy_changes.sort_by_key(|change| change.state);
+
+    Diff {
+        only_in_a: only_in_a,
+        only_in_b: only_in_b,
+        value_changes: value_changes,
+        greedy_changes: greedy_changes,
+    }
+}
+
+pub fn print_diff(diff: &Diff, top: usize) {
+    println!("states only in a: {}", diff.only_in_a.len());
+    for state in diff.only_in_a.iter() {
+        println!("  {:016x}", state);
+    }
+    println!("states only in b: {}", diff.only_in_b.len());
+    for state in diff.only_in_b.iter() {
+        println!("  {:016x}", state);
+    }
+    println!(
+        "largest value changes (top {} of {}):",
+        top.min(diff.value_changes.len()),
+        diff.value_changes.len()
+    );
+    for change in diff.value_changes.iter().take(top) {
+        println!(
+            "  {:016x} {:<20} {:>14.3} -> {:>14.3} ({:+.3})",
+            change.state,
+            format!("{:?}", change.command),
+            change.before,
+            change.after,
+            change.after - change.before
+        );
+    }
+    println!("greedy action changed: {}", diff.greedy_changes.len());
+    for change in diff.greedy_changes.iter() {
+        println!(
+            "  {:016x} {:?} -> {:?}",
+            change.state,
+            change.before,
+            change.after
+        );
+    }
+}
+
+//訪問回数の合計がmin_visits未満の状態を取り除き、取り除いた数を返す
+//古い形式から読んだ表は訪問回数がすべて0なので、全部消してしまわないようエラーにする
+pub fn prune(q_table: &mut QTable, min_visits: u64) -> Result<usize, String> {
+    let has_visits = q_table.values().any(|row| row.values().any(|q| q.visits > 0));
+    if min_visits > 0 && !q_table.is_empty() && !has_visits {
+        return Err("table has no visit counts (legacy format?); refusing to prune".to_string());
+    }
+    let before = q_table.len();
+    q_table.retain(|_, row| row.values().map(|q| q.visits).sum::<u64>() >= min_visits);
+    Ok(before - q_table.len())
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use serde_json;
+use field::Field;
+
+//CSVでもJSON Linesでも同じ綴り(cleared, game_over, time_limit)で書く
+#[derive(Copy, Clone, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Cleared,
+    GameOver,
+    TimeLimit,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Outcome::Cleared => "cleared",
+            Outcome::GameOver => "game_over",
+            Outcome::TimeLimit => "time_limit",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Serialize)]
+pub struct EpisodeRecord {
+    pub episode: usize,
+    pub seed: u64,
+    pub steps: u64,
+    pub score: u64,
+    pub total_reward: f64,
+    pub kills: u64,
+    pub max_chain: u64,
+    pub epsilon: f64,
+    pub states: usize,
+    pub outcome: Outcome,
+}
+
+impl EpisodeRecord {
+    //エピソードを終えたFieldから記録を作る
+    pub fn from_field(
+        episode: usize,
+        seed: u64,
+        field: &Field,
+        total_reward: f64,
+        epsilon: f64,
+        states: usize,
+    ) -> EpisodeRecord {
+        EpisodeRecord {
+            episode: episode,
+            seed: seed,
+            steps: field.tick,
+            score: field.score,
+            total_reward: total_reward,
+            kills: field.kills,
+            max_chain: field.max_chain,
+            epsilon: epsilon,
+            states: states,
+            outcome: field.outcome(),
+        }
+    }
+}
+
+const CSV_HEADER: &str = "episode,seed,steps,score,total_reward,kills,max_chain,epsilon,states,outcome";
+
+enum LogFormat {
+    Csv,
+    JsonLines,
+}
+
+//拡張子が .jsonl なら JSON Lines、それ以外はヘッダ付きCSVで追記する
+pub struct TrainingLog {
+    file: File,
+    format: LogFormat,
+}
+
+impl TrainingLog {
+    pub fn open(path: &str) -> io::Result<TrainingLog> {
+        let format = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("jsonl") => LogFormat::JsonLines,
+            _ => LogFormat::Csv,
+        };
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if let LogFormat::Csv = format {
+            if file.metadata()?.len() == 0 {
+                writeln!(file, "{}", CSV_HEADER)?;
+            }
+        }
+        Ok(TrainingLog {
+            file: file,
+            format: format,
+        })
+    }
+
+    pub fn write(&mut self, record: &EpisodeRecord) -> io::Result<()> {
+        match self.format {
+            LogFormat::Csv => {
+                writeln!(
+                    self.file,
+                    "{},{},{},{},{},{},{},{},{},{}",
+                    record.episode,
+                    record.seed,
+                    record.steps,
+                    record.score,
+                    record.total_reward,
+                    record.kills,
+                    record.max_chain,
+                    record.epsilon,
+                    record.states,
+                    record.outcome
+                )?
+            }
+            LogFormat::JsonLines => {
+                serde_json::to_writer(&mut self.file, record)?;
+                writeln!(self.file)?;
+            }
+        }
+        self.file.flush()
+    }
+}
+
+//CSVの行でもJSON Linesの行でも、先頭のepisodeを読む。ヘッダならNone
+fn line_episode(line: &str) -> Option<usize> {
+    if line.starts_with('{') {
+        let value: serde_json::Value = serde_json::from_str(line).ok()?;
+        value.get("episode")?.as_u64().map(|episode| episode as usize)
+    } else {
+        line.split(',').next()?.parse().ok()
+    }
+}
+
+//チェックポイントから再開するとき、落ちた学習がepisode以降に書いた行を消して、取り除いた行数を返す
+//末尾からepisodeが1ずつ減りながら続く行だけを消すので、前の学習の行は残る
+pub fn truncate_from(path: &str, episode: usize) -> io::Result<usize> {
+    let mut content = String::new();
+    match File::open(path) {
+        Ok(mut file) => file.read_to_string(&mut content)?,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let mut keep = lines.len();
+    let mut expected = None;
+    while keep > 0 {
+        match line_episode(lines[keep - 1]) {
+            Some(e) if e >= episode && expected.map_or(true, |expected| expected == e) => {
+                keep -= 1;
+                if e == episode {
+                    break;
+                }
+                expected = Some(e - 1);
+            }
+            _ => break,
+        }
+    }
+    let removed = lines.len() - keep;
+    if removed > 0 {
+        let tmp_path = format!("{}.tmp", path);
+        {
+            let mut file = File::create(&tmp_path)?;
+            for line in lines[..keep].iter() {
+                writeln!(file, "{}", line)?;
+            }
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    //episodeの並びからCSVのログを作る
+    fn write_log(name: &str, episodes: &[usize]) -> String {
+        let path = env::temp_dir().join(name).to_str().unwrap().to_string();
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "{}", CSV_HEADER).unwrap();
+        for episode in episodes {
+            writeln!(file, "{},0,10,0,0,0,0,0.1,5,game_over", episode).unwrap();
+        }
+        path
+    }
+
+    fn read_episodes(path: &str) -> Vec<usize> {
+        let mut content = String::new();
+        File::open(path).unwrap().read_to_string(&mut content).unwrap();
+        let episodes = content.lines().filter_map(line_episode).collect();
+        fs::remove_file(path).unwrap();
+        episodes
+    }
+
+    #[test]
+    fn nothing_past_the_checkpoint() {
+        let path = write_log("shot_truncate_none.csv", &[0, 1, 2, 3]);
+        assert_eq!(truncate_from(&path, 4).unwrap(), 0);
+        assert_eq!(read_episodes(&path), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn removes_rows_past_the_checkpoint() {
+        let path = write_log("shot_truncate_past.csv", &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(truncate_from(&path, 3).unwrap(), 3);
+        assert_eq!(read_episodes(&path), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn keeps_a_later_run_that_restarts_at_zero() {
+        //チェックポイントの学習の後に、0から数え直す別の学習が追記されている
+        let path = write_log("shot_truncate_restart.csv", &[0, 1, 2, 3, 4, 5, 0, 1]);
+        assert_eq!(truncate_from(&path, 3).unwrap(), 0);
+        assert_eq!(read_episodes(&path), vec![0, 1, 2, 3, 4, 5, 0, 1]);
+    }
+
+    #[test]
+    fn missing_log_is_not_an_error() {
+        let path = env::temp_dir().join("shot_truncate_missing.csv");
+        assert_eq!(truncate_from(path.to_str().unwrap(), 3).unwrap(), 0);
+    }
+}
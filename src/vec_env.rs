@@ -0,0 +1,61 @@
+use field::{Field, EPISODE_TICK_LIMIT, OBSERVATION_SIZE};
+use file_reader::Pattern;
+use object::Command;
+
+pub struct StepResult {
+    //環境数 × OBSERVATION_SIZE を行優先で並べたもの
+    pub observations: Vec<f32>,
+    pub rewards: Vec<f64>,
+    pub dones: Vec<bool>,
+}
+
+//複数のFieldをまとめて1コマンドずつ進める環境
+//終わったFieldはその場でリセットし、返す観測は次のエピソードの最初のものになる
+pub struct VecEnv {
+    fields: Vec<Field>,
+}
+
+impl VecEnv {
+    pub fn new(count: usize, width: u32, height: u32, pattern: &Pattern) -> VecEnv {
+        let fields = (0..count).map(|_| Field::new(width, height, pattern)).collect();
+        VecEnv { fields: fields }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn reset(&mut self) -> Vec<f32> {
+        for field in self.fields.iter_mut() {
+            field.reset();
+        }
+        self.observations()
+    }
+
+    pub fn step(&mut self, commands: &[Command]) -> StepResult {
+        assert_eq!(commands.len(), self.fields.len());
+        let mut rewards = Vec::with_capacity(self.fields.len());
+        let mut dones = Vec::with_capacity(self.fields.len());
+        for (field, &command) in self.fields.iter_mut().zip(commands) {
+            rewards.push(field.step(command));
+            let done = field.game_end || field.tick >= EPISODE_TICK_LIMIT;
+            if done {
+                field.reset();
+            }
+            dones.push(done);
+        }
+        StepResult {
+            observations: self.observations(),
+            rewards: rewards,
+            dones: dones,
+        }
+    }
+
+    fn observations(&self) -> Vec<f32> {
+        let mut observations = Vec::with_capacity(self.fields.len() * OBSERVATION_SIZE);
+        for field in self.fields.iter() {
+            observations.extend_from_slice(&field.observation());
+        }
+        observations
+    }
+}